@@ -0,0 +1,246 @@
+//! Reader for service bindings following the [servicebinding.io](https://servicebinding.io/spec/core/1.0.0/) spec
+//!
+//! Kubernetes based platforms like Korifi or Paketo buildpacks mount every binding as directory `$SERVICE_BINDING_ROOT/<name>/` containing a `type` file, an optional `provider` file and one file per credential key.
+use crate::constants::SERVICE_BINDING_ROOT;
+use crate::enums::{Error, FileError};
+use crate::index::ServiceMap;
+use crate::models::Service;
+use guid_create::GUID;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+
+const TYPE: &str = "type";
+const PROVIDER: &str = "provider";
+
+/// Reads all bindings below `root` into the shape of `VCAP_SERVICES`, keyed by their type
+///
+/// Each binding becomes a [`Service`] with
+/// - `name`, `instance_name` and `binding_name` set to the name of the directory
+/// - `label` set to the type and `tags` to the type and the provider, if there is one
+/// - `credentials` holding every other file as a string, binary files like keystores are left out and have to be read from the binding directory
+/// - nil guids and no plan, as bindings don't carry them
+///
+/// Hidden entries, like the `..data` links of mounted kubernetes secrets, are skipped.
+pub fn read_binding_root(root: impl AsRef<Path>) -> Result<ServiceMap, Error> {
+    let root = root.as_ref();
+    let mut services = ServiceMap::new();
+
+    for binding in read_dir(root)? {
+        if !binding.is_dir() {
+            continue;
+        }
+        let service = read_binding(&binding)?;
+        services
+            .entry(service.label.clone())
+            .or_default()
+            .push(service);
+    }
+
+    for services in services.values_mut() {
+        services.sort_by(|left, right| left.name.cmp(&right.name));
+    }
+    Ok(services)
+}
+
+/// Reads a single binding directory
+pub fn read_binding(path: impl AsRef<Path>) -> Result<Service, Error> {
+    let path = path.as_ref();
+    let name = file_name(path).unwrap_or_default();
+
+    let mut binding_type = None;
+    let mut provider = None;
+    let mut credentials = Map::new();
+
+    for entry in read_dir(path)? {
+        if !entry.is_file() {
+            continue;
+        }
+        let Some(key) = file_name(&entry) else {
+            continue;
+        };
+        let value = match fs::read(&entry) {
+            Ok(value) => match String::from_utf8(value) {
+                Ok(value) => value,
+                Err(_) => continue,
+            },
+            Err(err) => {
+                return Err(Error::FileUnreadable(
+                    SERVICE_BINDING_ROOT.to_string(),
+                    FileError::new(&entry, err),
+                ))
+            }
+        };
+
+        match key.as_str() {
+            TYPE => binding_type = Some(value.trim().to_string()),
+            PROVIDER => provider = Some(value.trim().to_string()),
+            _ => {
+                credentials.insert(key, Value::String(value));
+            }
+        }
+    }
+
+    let Some(binding_type) = binding_type.filter(|binding_type| !binding_type.is_empty()) else {
+        return Err(Error::EnvMalformed(
+            SERVICE_BINDING_ROOT.to_string(),
+            format!("Binding {name:?} has no type"),
+        ));
+    };
+
+    let mut tags = vec![binding_type.clone()];
+    tags.extend(provider);
+
+    Ok(Service {
+        binding_guid: GUID::default(),
+        binding_name: Some(name.clone()),
+        instance_guid: GUID::default(),
+        instance_name: name.clone(),
+        name,
+        label: binding_type,
+        tags,
+        plan: String::new(),
+        credentials: Value::Object(credentials),
+        syslog_drain_url: None,
+        volume_mounts: Vec::new(),
+    })
+}
+
+fn file_name(path: &Path) -> Option<String> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_string)
+}
+
+/// Lists the entries of `path` in order of their names, leaving out hidden ones
+fn read_dir(path: &Path) -> Result<Vec<std::path::PathBuf>, Error> {
+    let unreadable =
+        |err| Error::FileUnreadable(SERVICE_BINDING_ROOT.to_string(), FileError::new(path, err));
+
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(path).map_err(unreadable)? {
+        let entry = entry.map_err(unreadable)?.path();
+        if file_name(&entry).is_some_and(|name| !name.starts_with('.')) {
+            entries.push(entry);
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::CfEnv;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::Path;
+
+    fn binding(root: &Path, name: &str, entries: &[(&str, &str)]) {
+        let directory = root.join(name);
+        fs::create_dir_all(&directory).unwrap();
+        for (key, value) in entries {
+            fs::write(directory.join(key), value).unwrap();
+        }
+    }
+
+    fn root() -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        binding(
+            root.path(),
+            "orders-db",
+            &[
+                ("type", "postgresql\n"),
+                ("provider", "bitnami"),
+                ("host", "orders.db.svc"),
+                ("port", "5432"),
+                ("username", "orders"),
+                ("password", "secret"),
+            ],
+        );
+        binding(
+            root.path(),
+            "cache",
+            &[("type", "redis"), ("host", "cache.svc")],
+        );
+        fs::create_dir_all(root.path().join("..2024_01_01")).unwrap();
+        fs::write(root.path().join("cache").join("..data"), "").unwrap();
+        fs::write(
+            root.path().join("orders-db").join("keystore.p12"),
+            [0x30, 0x82, 0xff, 0xfe, 0x00],
+        )
+        .unwrap();
+        root
+    }
+
+    #[test]
+    fn reads_bindings() {
+        let root = root();
+        let services = crate::read_binding_root(root.path()).unwrap();
+
+        assert_eq!(services.len(), 2);
+        let postgres = &services["postgresql"][0];
+        assert_eq!(postgres.name, "orders-db");
+        assert_eq!(postgres.tags, vec!["postgresql", "bitnami"]);
+        assert_eq!(postgres.credentials["port"], "5432");
+        assert!(postgres.credentials.get("type").is_none());
+        assert!(postgres.credentials.get("keystore.p12").is_none());
+        assert_eq!(
+            services["redis"][0].credentials.as_object().unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn getters_use_binding_root() {
+        #[derive(serde::Deserialize)]
+        struct Credentials {
+            host: String,
+            port: String,
+        }
+
+        let root = root();
+        let env = CfEnv::new(HashMap::from([(
+            "SERVICE_BINDING_ROOT",
+            root.path().to_str().unwrap(),
+        )]));
+
+        assert_eq!(
+            env.get_services_source().unwrap(),
+            crate::ServicesSource::BindingRoot(root.path().to_path_buf())
+        );
+        let service = env.get_service_by_name::<Credentials>("orders-db").unwrap();
+        assert_eq!(service.credentials.host, "orders.db.svc");
+        assert_eq!(service.credentials.port, "5432");
+        assert_eq!(
+            env.get_services_by_type::<serde_json::Value>("redis")
+                .unwrap()[0]
+                .name,
+            "cache"
+        );
+    }
+
+    #[test]
+    fn vcap_services_take_precedence() {
+        let root = root();
+        let env = CfEnv::new(HashMap::from([
+            ("SERVICE_BINDING_ROOT", root.path().to_str().unwrap()),
+            ("VCAP_SERVICES", "{}"),
+        ]));
+
+        assert_eq!(
+            env.get_services_source().unwrap(),
+            crate::ServicesSource::Env
+        );
+    }
+
+    #[test]
+    fn binding_without_type() {
+        let root = tempfile::tempdir().unwrap();
+        binding(root.path(), "broken", &[("host", "somewhere")]);
+
+        assert!(matches!(
+            crate::read_binding_root(root.path()),
+            Err(crate::Error::EnvMalformed(..))
+        ));
+    }
+}
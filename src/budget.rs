@@ -0,0 +1,242 @@
+use crate::enums::Error;
+use crate::models::MemoryLimit;
+
+/// Splits a memory limit into named allocations, like the memory calculator of the Java buildpack
+///
+/// Fixed reservations, thread stacks and percentage pools are taken from the limit first, the remainder goes to the allocation given to [`MemoryBudget::remainder`], e.g. a cache.
+///
+/// ```no_run
+/// use cf_env::MemoryLimit;
+///
+/// let allocations = cf_env::get_memory_budget()
+///     .unwrap()
+///     .headroom("64M".parse().unwrap())
+///     .threads("stacks", "1M".parse().unwrap(), 200)
+///     .pool("buffers", 10.0, "32M".parse().unwrap())
+///     .remainder("cache", "128M".parse().unwrap())
+///     .calculate()
+///     .unwrap();
+///
+/// let cache_size = allocations.get("cache").unwrap().as_bytes();
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryBudget {
+    limit: MemoryLimit,
+    reservations: Vec<Reservation>,
+    remainder: Option<(String, MemoryLimit)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Reservation {
+    Fixed(String, MemoryLimit),
+    Threads(String, MemoryLimit, u128),
+    Pool(String, f64, MemoryLimit),
+}
+
+/// The name of the allocation [`MemoryBudget::headroom`] reserves
+pub const HEADROOM: &str = "headroom";
+
+impl MemoryBudget {
+    pub fn new(limit: MemoryLimit) -> Self {
+        Self {
+            limit,
+            reservations: Vec::new(),
+            remainder: None,
+        }
+    }
+
+    /// Reserves `size` as safety margin nothing else gets, named [`HEADROOM`]
+    pub fn headroom(self, size: MemoryLimit) -> Self {
+        self.fixed(HEADROOM, size)
+    }
+
+    /// Reserves a fixed `size`
+    pub fn fixed(mut self, name: impl Into<String>, size: MemoryLimit) -> Self {
+        self.reservations
+            .push(Reservation::Fixed(name.into(), size));
+        self
+    }
+
+    /// Reserves `stack_size` for each of `count` threads
+    pub fn threads(
+        mut self,
+        name: impl Into<String>,
+        stack_size: MemoryLimit,
+        count: u128,
+    ) -> Self {
+        self.reservations
+            .push(Reservation::Threads(name.into(), stack_size, count));
+        self
+    }
+
+    /// Reserves `percent` of the whole limit, but at least `minimum`
+    pub fn pool(mut self, name: impl Into<String>, percent: f64, minimum: MemoryLimit) -> Self {
+        self.reservations
+            .push(Reservation::Pool(name.into(), percent, minimum));
+        self
+    }
+
+    /// Gives everything not reserved otherwise to `name`, which needs at least `minimum`
+    pub fn remainder(mut self, name: impl Into<String>, minimum: MemoryLimit) -> Self {
+        self.remainder = Some((name.into(), minimum));
+        self
+    }
+
+    pub fn limit(&self) -> MemoryLimit {
+        self.limit
+    }
+
+    /// Calculates the allocations in the order they were configured, the remainder last
+    ///
+    /// Fails with [`Error::MemoryInsufficient`] if the limit is too small for the reservations and the minimum of the remainder.
+    pub fn calculate(&self) -> Result<MemoryAllocations, Error> {
+        let limit = self.limit.as_bytes();
+        let mut allocations = Vec::with_capacity(self.reservations.len() + 1);
+
+        for reservation in &self.reservations {
+            let (name, bytes) = match reservation {
+                Reservation::Fixed(name, size) => (name, size.as_bytes()),
+                Reservation::Threads(name, stack_size, count) => {
+                    (name, stack_size.as_bytes().saturating_mul(*count))
+                }
+                Reservation::Pool(name, percent, minimum) => {
+                    let share = (limit as f64 * percent.max(0.0) / 100.0) as u128;
+                    (name, share.max(minimum.as_bytes()))
+                }
+            };
+            allocations.push((name.clone(), MemoryLimit::from_bytes(bytes)));
+        }
+
+        let reserved = allocations
+            .iter()
+            .fold(0u128, |sum, (_, size)| sum.saturating_add(size.as_bytes()));
+        let minimum = match &self.remainder {
+            Some((_, minimum)) => minimum.as_bytes(),
+            None => 0,
+        };
+        if reserved.saturating_add(minimum) > limit {
+            return Err(Error::MemoryInsufficient(
+                self.limit,
+                MemoryLimit::from_bytes(reserved.saturating_add(minimum)),
+            ));
+        }
+
+        if let Some((name, _)) = &self.remainder {
+            allocations.push((name.clone(), MemoryLimit::from_bytes(limit - reserved)));
+        }
+        Ok(MemoryAllocations {
+            limit: self.limit,
+            allocations,
+        })
+    }
+}
+
+/// The result of a [`MemoryBudget`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryAllocations {
+    limit: MemoryLimit,
+    allocations: Vec<(String, MemoryLimit)>,
+}
+
+impl MemoryAllocations {
+    /// Get's the size allocated to `name`
+    pub fn get(&self, name: &str) -> Option<MemoryLimit> {
+        self.allocations
+            .iter()
+            .find(|(allocation, _)| allocation == name)
+            .map(|(_, size)| *size)
+    }
+
+    /// Iterates the allocations in the order they were configured
+    pub fn iter(&self) -> impl Iterator<Item = (&str, MemoryLimit)> {
+        self.allocations
+            .iter()
+            .map(|(name, size)| (name.as_str(), *size))
+    }
+
+    /// The sum of all allocations
+    pub fn allocated(&self) -> MemoryLimit {
+        self.allocations
+            .iter()
+            .fold(MemoryLimit::default(), |sum, (_, size)| sum + *size)
+    }
+
+    /// What's left of the limit, zero if there is a remainder
+    pub fn unallocated(&self) -> MemoryLimit {
+        self.limit - self.allocated()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CfEnv, MemoryBudget, MemoryLimit};
+    use std::collections::HashMap;
+
+    fn limit(input: &str) -> MemoryLimit {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn allocates_remainder() {
+        let env = CfEnv::new(HashMap::from([("MEMORY_LIMIT", "1G")]));
+
+        let allocations = env
+            .get_memory_budget()
+            .unwrap()
+            .headroom(limit("64M"))
+            .threads("stacks", limit("1M"), 100)
+            .pool("buffers", 10.0, limit("32M"))
+            .fixed("metaspace", limit("100M"))
+            .remainder("cache", limit("128M"))
+            .calculate()
+            .unwrap();
+
+        assert_eq!(allocations.get("headroom"), Some(limit("64M")));
+        assert_eq!(allocations.get("stacks"), Some(limit("100M")));
+        assert_eq!(
+            allocations.get("buffers").unwrap().as_bytes(),
+            (1 << 30) / 10
+        );
+        assert_eq!(
+            allocations.get("cache").unwrap(),
+            limit("1G") - limit("264M") - allocations.get("buffers").unwrap()
+        );
+        assert_eq!(allocations.allocated(), limit("1G"));
+        assert_eq!(allocations.unallocated(), MemoryLimit::default());
+        assert_eq!(
+            allocations.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            vec!["headroom", "stacks", "buffers", "metaspace", "cache"]
+        );
+    }
+
+    #[test]
+    fn pool_minimum() {
+        let allocations = MemoryBudget::new(limit("256M"))
+            .pool("buffers", 10.0, limit("64M"))
+            .calculate()
+            .unwrap();
+
+        assert_eq!(allocations.get("buffers"), Some(limit("64M")));
+        assert_eq!(allocations.unallocated(), limit("192M"));
+        assert_eq!(allocations.get("cache"), None);
+    }
+
+    #[test]
+    fn too_small() {
+        let error = MemoryBudget::new(limit("256M"))
+            .headroom(limit("64M"))
+            .threads("stacks", limit("1M"), 200)
+            .remainder("cache", limit("64M"))
+            .calculate()
+            .unwrap_err();
+
+        assert_eq!(
+            error,
+            crate::Error::MemoryInsufficient(limit("256M"), limit("328M"))
+        );
+        assert_eq!(
+            error.to_string(),
+            "the memory limit of 256M is too small, the budget needs at least 328M"
+        );
+    }
+}
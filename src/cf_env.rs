@@ -0,0 +1,1100 @@
+use crate::bindings::read_binding_root;
+use crate::budget::MemoryBudget;
+use crate::cgroup::{ContainerLimits, LimitMismatch};
+use crate::constants::*;
+use crate::database::{DatabaseConfig, DatabaseKind};
+use crate::disk::DiskUsage;
+use crate::enums::{parse_json, Error, FileError, ServicesSource};
+#[cfg(feature = "identity")]
+use crate::identity::{
+    parse_key_file, read_certificates_dir, InstanceCertificate, InstanceIdentity,
+};
+use crate::index::{ServiceIndex, ServiceMap};
+use crate::lenient::{parse_services_lenient, LenientApplication, ServiceWarning};
+use crate::locale::PosixLocale;
+use crate::models::{Application, InstancePorts, MemoryLimit, PlatformOptions, Service};
+use crate::query::ServiceQuery;
+use crate::source::{EnvSnapshot, EnvSource, ProcessEnv};
+use guid_create::GUID;
+use http::Uri;
+use locale_types::Locale;
+#[cfg(feature = "identity")]
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use serde::de::DeserializeOwned;
+use std::borrow::Cow;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+
+/// Typed access to the Cloud Foundry environment variables of an [`EnvSource`]
+///
+/// The free functions of this crate are thin wrappers over `CfEnv<ProcessEnv>`. Use another source to read from somewhere else than the process environment, e.g. a `HashMap` in tests.
+///
+/// ```
+/// use std::collections::HashMap;
+/// use cf_env::CfEnv;
+///
+/// let env = CfEnv::new(HashMap::from([("PORT", "8080")]));
+///
+/// assert_eq!(env.get_port().unwrap(), 8080);
+/// ```
+///
+/// By default `VCAP_SERVICES` and `VCAP_APPLICATION` are parsed on every call. Use [`CfEnv::load`] or [`CfEnv::load_from`] to parse them once up front, which is what you want for lookups on hot paths.
+///
+/// Services are parsed strictly unless [`CfEnv::lenient`] is used, see [`crate::lenient`].
+#[derive(Clone, Debug, Default)]
+pub struct CfEnv<S = ProcessEnv> {
+    source: S,
+    lenient: bool,
+    cache: Option<Cache>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Cache {
+    services: Option<ServiceIndex>,
+    services_source: Option<ServicesSource>,
+    service_warnings: Vec<ServiceWarning>,
+    application: Option<LenientApplication>,
+    /// The strict model of `application`, `None` if it lacks fields, which only gets past a lenient load
+    application_info: Option<Application>,
+}
+
+impl CfEnv<EnvSnapshot> {
+    /// Takes a snapshot of the process environment and parses `VCAP_SERVICES` and `VCAP_APPLICATION` once
+    ///
+    /// ```no_run
+    /// let env = cf_env::CfEnv::load().unwrap();
+    ///
+    /// let service = env.get_service_by_name::<serde_json::Value>("my_db").unwrap();
+    /// let postgres = env.service_index().unwrap().by_tag("postgres");
+    /// ```
+    pub fn load() -> Result<Self, Error> {
+        Self::load_from(EnvSnapshot::capture())
+    }
+
+    /// Like [`CfEnv::load`], but parses the services leniently
+    pub fn load_lenient() -> Result<Self, Error> {
+        Self::load_lenient_from(EnvSnapshot::capture())
+    }
+}
+
+impl<S: EnvSource> CfEnv<S> {
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            lenient: false,
+            cache: None,
+        }
+    }
+
+    /// Parses services leniently, giving version dependent fields a default and skipping broken entries instead of failing
+    ///
+    /// What had to be worked around can be seen with [`CfEnv::get_services_with_warnings`].
+    pub fn lenient(mut self) -> Self {
+        self.lenient = true;
+        self
+    }
+
+    /// Parses `VCAP_SERVICES` and `VCAP_APPLICATION` of `source` once and keeps the results
+    ///
+    /// Fails if one of them is set but can't be parsed, if they aren't set the getters report them as not set.
+    pub fn load_from(source: S) -> Result<Self, Error> {
+        Self::new(source).into_loaded()
+    }
+
+    /// Like [`CfEnv::load_from`], but parses the services leniently
+    pub fn load_lenient_from(source: S) -> Result<Self, Error> {
+        Self::new(source).lenient().into_loaded()
+    }
+
+    fn into_loaded(self) -> Result<Self, Error> {
+        let (services, services_source, service_warnings) = match self.parse_services() {
+            Ok((services, services_source, service_warnings)) => (
+                Some(ServiceIndex::new(services)),
+                Some(services_source),
+                service_warnings,
+            ),
+            Err(Error::EnvNotSet(_)) => (None, None, Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let application = match self.parse_application_lenient() {
+            Ok(application) => Some(application),
+            Err(Error::EnvNotSet(_)) => None,
+            Err(e) => return Err(e),
+        };
+        let application_info = match (&application, self.lenient) {
+            (Some(application), false) => Some(application.to_application()?),
+            (Some(application), true) => application.to_application().ok(),
+            (None, _) => None,
+        };
+
+        Ok(Self {
+            source: self.source,
+            lenient: self.lenient,
+            cache: Some(Cache {
+                services,
+                services_source,
+                service_warnings,
+                application,
+                application_info,
+            }),
+        })
+    }
+
+    /// Whether `VCAP_SERVICES` and `VCAP_APPLICATION` were parsed up front
+    pub fn is_loaded(&self) -> bool {
+        self.cache.is_some()
+    }
+
+    /// Get's the underlying source of variables
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    fn var(&self, name: &'static str) -> Result<String, Error> {
+        match self.source.var(name) {
+            Some(value) => Ok(value),
+            None => Err(Error::EnvNotSet(name.to_string())),
+        }
+    }
+
+    /// Checks if `VCAP_APPLICATION` is defined, see [`crate::is_cf_env`]
+    pub fn is_cf_env(&self) -> bool {
+        self.source.var(VCAP_APPLICATION).is_some()
+    }
+
+    /// Get's the value from `CF_INSTANCE_ADDR` as a typed SocketAddr
+    pub fn get_instance_address(&self) -> Result<SocketAddr, Error> {
+        match self.var(CF_INSTANCE_ADDR)?.parse::<SocketAddr>() {
+            Ok(socket) => Ok(socket),
+            Err(_) => Err(Error::EnvMalformed(
+                CF_INSTANCE_ADDR.to_string(),
+                "Doesn't match the format of addr:ip".to_string(),
+            )),
+        }
+    }
+
+    /// Get's the instance identity certificate from the file at `CF_INSTANCE_CERT`
+    #[cfg(feature = "identity")]
+    pub fn get_instance_cert(&self) -> Result<InstanceCertificate, Error> {
+        InstanceCertificate::from_file(self.var(CF_INSTANCE_CERT)?)
+    }
+
+    /// Get's the private key of the instance identity certificate from the file at `CF_INSTANCE_KEY`
+    #[cfg(feature = "identity")]
+    pub fn get_instance_key(&self) -> Result<PrivateKeyDer<'static>, Error> {
+        parse_key_file(self.var(CF_INSTANCE_KEY)?)
+    }
+
+    /// Get's the instance identity certificate together with its private key
+    #[cfg(feature = "identity")]
+    pub fn get_instance_identity(&self) -> Result<InstanceIdentity, Error> {
+        Ok(InstanceIdentity {
+            certificate: self.get_instance_cert()?,
+            key: self.get_instance_key()?,
+        })
+    }
+
+    /// Get's the value from `CF_SYSTEM_CERT_PATH` as a typed PathBuf, falling back to [`DEFAULT_SYSTEM_CERT_PATH`]
+    pub fn get_system_cert_path(&self) -> PathBuf {
+        PathBuf::from(
+            self.source
+                .var(CF_SYSTEM_CERT_PATH)
+                .unwrap_or_else(|| DEFAULT_SYSTEM_CERT_PATH.to_string()),
+        )
+    }
+
+    /// Get's the platform CA certificates from the directory at `CF_SYSTEM_CERT_PATH`
+    #[cfg(feature = "identity")]
+    pub fn get_system_certificates(&self) -> Result<Vec<CertificateDer<'static>>, Error> {
+        read_certificates_dir(self.get_system_cert_path())
+    }
+
+    /// Builds a rustls server config for mutual tls from the instance identity, trusting the platform CAs for client certificates
+    #[cfg(feature = "rustls")]
+    pub fn get_tls_server_config(&self) -> Result<rustls::ServerConfig, Error> {
+        let roots = crate::tls::root_store(self.get_system_certificates()?)?;
+        self.get_instance_identity()?.server_config(roots)
+    }
+
+    /// Builds a rustls client config presenting the instance identity, trusting the platform CAs
+    #[cfg(feature = "rustls")]
+    pub fn get_tls_client_config(&self) -> Result<rustls::ClientConfig, Error> {
+        let roots = crate::tls::root_store(self.get_system_certificates()?)?;
+        self.get_instance_identity()?.client_config(roots)
+    }
+
+    /// Get's `credhub-uri` from `VCAP_PLATFORM_OPTIONS` as a typed Uri
+    #[cfg(feature = "credhub")]
+    pub fn get_credhub_uri(&self) -> Result<Uri, Error> {
+        match self
+            .get_platform_options()?
+            .credhub_uri
+            .and_then(|uri| uri.parse::<Uri>().ok())
+        {
+            Some(uri) => Ok(uri),
+            None => Err(Error::EnvMalformed(
+                VCAP_PLATFORM_OPTIONS.to_string(),
+                "Doesn't contain a valid credhub-uri".to_string(),
+            )),
+        }
+    }
+
+    /// Builds a client for the CredHub at `credhub-uri`, authenticated by the instance identity
+    #[cfg(feature = "credhub")]
+    pub fn get_credhub_client(&self) -> Result<crate::credhub::CredHubClient, Error> {
+        Ok(crate::credhub::CredHubClient::new(
+            self.get_credhub_uri()?,
+            std::sync::Arc::new(self.get_tls_client_config()?),
+        ))
+    }
+
+    /// Loads the environment if it isn't yet and replaces CredHub references in the services with the credentials, see [`crate::credhub`]
+    ///
+    /// CredHub is only called if there are references, so this is cheap for apps without.
+    #[cfg(feature = "credhub")]
+    pub fn resolve_credentials(self) -> Result<Self, Error> {
+        let mut env = if self.is_loaded() {
+            self
+        } else {
+            self.into_loaded()?
+        };
+
+        let services = match env.cache.as_ref().and_then(|cache| cache.services.as_ref()) {
+            Some(services) => services.to_map(),
+            None => return Ok(env),
+        };
+        if !crate::credhub::has_credhub_refs(&services) {
+            return Ok(env);
+        }
+
+        let resolved = env.get_credhub_client()?.interpolate(&services)?;
+        if let Some(cache) = &mut env.cache {
+            cache.services = Some(ServiceIndex::new(resolved));
+        }
+        Ok(env)
+    }
+
+    /// Starts watching the files at `CF_INSTANCE_CERT` and `CF_INSTANCE_KEY`, swapping in rotated certificates
+    #[cfg(feature = "reload")]
+    pub fn watch_instance_identity(
+        &self,
+        interval: std::time::Duration,
+    ) -> Result<crate::reload::CertificateWatcher, Error> {
+        let resolver = crate::reload::ReloadingResolver::new(
+            self.var(CF_INSTANCE_CERT)?,
+            self.var(CF_INSTANCE_KEY)?,
+        )?;
+        Ok(std::sync::Arc::new(resolver).watch(interval))
+    }
+
+    /// Get's the value from `CF_INSTANCE_GUID` as a typed GUID
+    pub fn get_instance_guid(&self) -> Result<GUID, Error> {
+        match GUID::parse(&self.var(CF_INSTANCE_GUID)?) {
+            Ok(result) => Ok(result),
+            Err(_) => Err(Error::EnvMalformed(
+                CF_INSTANCE_GUID.to_string(),
+                "Isn't a valid guid".to_string(),
+            )),
+        }
+    }
+
+    /// Get's the value from `CF_INSTANCE_INDEX` as a typed u128
+    pub fn get_instance_index(&self) -> Result<u128, Error> {
+        match self.var(CF_INSTANCE_INDEX)?.parse::<u128>() {
+            Ok(result) => Ok(result),
+            Err(_) => Err(Error::EnvMalformed(
+                CF_INSTANCE_INDEX.to_string(),
+                "Ins't a valid positive (u128) number".to_string(),
+            )),
+        }
+    }
+
+    /// Get's the value from `CF_INSTANCE_IP` as a typed IpAddr
+    pub fn get_instance_ip(&self) -> Result<IpAddr, Error> {
+        match self.var(CF_INSTANCE_IP)?.parse::<IpAddr>() {
+            Ok(result) => Ok(result),
+            Err(_) => Err(Error::EnvMalformed(
+                CF_INSTANCE_IP.to_string(),
+                "Ins't a valid ip address".to_string(),
+            )),
+        }
+    }
+
+    /// Get's the value from `CF_INSTANCE_INTERNAL_IP` as a typed IpAddr
+    pub fn get_instance_internal_ip(&self) -> Result<IpAddr, Error> {
+        match self.var(CF_INSTANCE_INTERNAL_IP)?.parse::<IpAddr>() {
+            Ok(result) => Ok(result),
+            Err(_) => Err(Error::EnvMalformed(
+                CF_INSTANCE_INTERNAL_IP.to_string(),
+                "Ins't a valid ip address".to_string(),
+            )),
+        }
+    }
+
+    /// Get's the value from `CF_INSTANCE_PORT` as a typed u16
+    pub fn get_instance_port(&self) -> Result<u16, Error> {
+        match self.var(CF_INSTANCE_PORT)?.parse::<u16>() {
+            Ok(result) => Ok(result),
+            Err(_) => Err(Error::EnvMalformed(
+                CF_INSTANCE_PORT.to_string(),
+                "Ins't a valid positive (u16) number".to_string(),
+            )),
+        }
+    }
+
+    /// Get's the value from `CF_INSTANCE_PORTS` as typed port mappings
+    pub fn get_instance_ports(&self) -> Result<InstancePorts, Error> {
+        match parse_json::<InstancePorts>(&self.var(CF_INSTANCE_PORTS)?) {
+            Ok(value) => Ok(value),
+            Err(err) => Err(Error::JsonMalformed(CF_INSTANCE_PORTS.to_string(), err)),
+        }
+    }
+
+    /// Get's the value from `DATABASE_URL` as a typed Uri
+    pub fn get_database_url(&self) -> Result<Uri, Error> {
+        match self.var(DATABASE_URL)?.parse::<Uri>() {
+            Ok(result) => Ok(result),
+            Err(_) => Err(Error::EnvMalformed(
+                DATABASE_URL.to_string(),
+                "Ins't a valid uri".to_string(),
+            )),
+        }
+    }
+
+    /// Get's the database connection info, see [`crate::get_database_config`]
+    pub fn get_database_config(&self) -> Result<DatabaseConfig, Error> {
+        if let Some(url) = self.source.var(DATABASE_URL) {
+            return DatabaseConfig::parse(DATABASE_URL, &url);
+        }
+
+        let services = match self.service_index() {
+            Ok(services) => services,
+            Err(Error::EnvNotSet(_)) => return Err(Error::EnvNotSet(DATABASE_URL.to_string())),
+            Err(err) => return Err(err),
+        };
+        let service = services
+            .iter()
+            .find(|service| DatabaseKind::of_service(service).is_relational());
+        match service {
+            Some(service) => DatabaseConfig::from_service(service),
+            None => Err(Error::EnvNotSet(DATABASE_URL.to_string())),
+        }
+    }
+
+    /// Get's the database connection info from the credentials of the service named `name`
+    pub fn get_database_config_for(&self, name: &str) -> Result<DatabaseConfig, Error> {
+        match self.service_index()?.by_name(name) {
+            Some(service) => DatabaseConfig::from_service(service),
+            None => Err(Error::ServiceNotPresent(name.to_string())),
+        }
+    }
+
+    /// Builds sqlx Postgres options from `DATABASE_URL`, or else from the first bound Postgres service by service type name, see [`Service::pg_connect_options`]
+    #[cfg(feature = "sqlx")]
+    pub fn get_pg_connect_options(&self) -> Result<sqlx::postgres::PgConnectOptions, Error> {
+        if let Some(url) = self.source.var(DATABASE_URL) {
+            return DatabaseConfig::parse(DATABASE_URL, &url)?.pg_connect_options(DATABASE_URL);
+        }
+        self.first_database_service(DatabaseKind::Postgres)?
+            .pg_connect_options()
+    }
+
+    /// Builds sqlx MySQL options from `DATABASE_URL`, or else from the first bound MySQL service by service type name, see [`Service::mysql_connect_options`]
+    #[cfg(feature = "sqlx")]
+    pub fn get_mysql_connect_options(&self) -> Result<sqlx::mysql::MySqlConnectOptions, Error> {
+        if let Some(url) = self.source.var(DATABASE_URL) {
+            return DatabaseConfig::parse(DATABASE_URL, &url)?.mysql_connect_options(DATABASE_URL);
+        }
+        self.first_database_service(DatabaseKind::MySql)?
+            .mysql_connect_options()
+    }
+
+    #[cfg(feature = "sqlx")]
+    fn first_database_service(&self, kind: DatabaseKind) -> Result<Service, Error> {
+        let services = match self.service_index() {
+            Ok(services) => services,
+            Err(Error::EnvNotSet(_)) => return Err(Error::EnvNotSet(DATABASE_URL.to_string())),
+            Err(err) => return Err(err),
+        };
+        let service = services
+            .iter()
+            .find(|service| DatabaseKind::of_service(service) == kind);
+        match service {
+            Some(service) => Ok(service.clone()),
+            None => Err(Error::EnvNotSet(DATABASE_URL.to_string())),
+        }
+    }
+
+    /// Builds sqlx pool options sized by `MEMORY_LIMIT` and the plan of `service`, see [`crate::pool_max_connections`]
+    ///
+    /// A `MEMORY_LIMIT` which isn't set is left out.
+    #[cfg(feature = "sqlx")]
+    pub fn get_pool_options<DB: sqlx::Database>(
+        &self,
+        service: Option<&Service>,
+    ) -> Result<sqlx::pool::PoolOptions<DB>, Error> {
+        let memory_limit = match self.get_memory_limit() {
+            Ok(memory_limit) => Some(memory_limit),
+            Err(Error::EnvNotSet(_)) => None,
+            Err(e) => return Err(e),
+        };
+        Ok(crate::connect_options::pool_options(
+            memory_limit,
+            service.map(|service| service.plan.as_str()),
+        ))
+    }
+
+    /// Get's the value from `HOME` as a typed PathBuf
+    pub fn get_home(&self) -> Result<PathBuf, Error> {
+        Ok(PathBuf::from(self.var(HOME)?))
+    }
+
+    /// Get's the value from `LANG` as a typed Locale
+    ///
+    /// Values like `C.UTF-8` or ones with a modifier can't be represented as Locale, [`CfEnv::get_locale`] handles those too.
+    pub fn get_lang(&self) -> Result<Locale, Error> {
+        match PosixLocale::parse(LANG, &self.var(LANG)?)?.to_locale() {
+            Some(locale) => Ok(locale),
+            None => Err(Error::EnvMalformed(
+                LANG.to_string(),
+                "Ins't a valid locale".to_string(),
+            )),
+        }
+    }
+
+    /// Get's the locale from the first set of `LC_ALL`, `LC_MESSAGES` and `LANG`, see [`crate::get_locale`]
+    ///
+    /// Empty variables count as not set, an invalid value is an error and doesn't fall back to the next one.
+    pub fn get_locale(&self) -> Result<PosixLocale, Error> {
+        for name in [LC_ALL, LC_MESSAGES, LANG] {
+            match self.source.var(name) {
+                Some(value) if !value.trim().is_empty() => return PosixLocale::parse(name, &value),
+                _ => {}
+            }
+        }
+        Err(Error::EnvNotSet(LANG.to_string()))
+    }
+
+    /// Get's the value from `MEMORY_LIMIT` as a typed MemoryLimit
+    pub fn get_memory_limit(&self) -> Result<MemoryLimit, Error> {
+        match MemoryLimit::from_string(self.var(MEMORY_LIMIT)?, MEMORY_LIMIT.to_string()) {
+            Ok(result) => Ok(result),
+            Err(_) => Err(Error::EnvMalformed(
+                MEMORY_LIMIT.to_string(),
+                "Ins't a valid memory size formatted after '<size><unit>'".to_string(),
+            )),
+        }
+    }
+
+    /// Get's a [`MemoryBudget`] over the value from `MEMORY_LIMIT`, see [`crate::get_memory_budget`]
+    pub fn get_memory_budget(&self) -> Result<MemoryBudget, Error> {
+        Ok(MemoryBudget::new(self.get_memory_limit()?))
+    }
+
+    /// Compares `MEMORY_LIMIT` and the limits of `VCAP_APPLICATION` with the ones the container enforces, see [`ContainerLimits::compare`]
+    ///
+    /// Variables which aren't set are left out.
+    pub fn check_container_limits(
+        &self,
+        limits: &ContainerLimits,
+    ) -> Result<Vec<LimitMismatch>, Error> {
+        let memory_limit = match self.get_memory_limit() {
+            Ok(memory_limit) => Some(memory_limit),
+            Err(Error::EnvNotSet(_)) => None,
+            Err(e) => return Err(e),
+        };
+        let application_limits = match self.get_application_lenient() {
+            Ok(application) => application.limits.unwrap_or_default(),
+            Err(Error::EnvNotSet(_)) => Default::default(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(limits.compare(
+            memory_limit,
+            application_limits.mem_bytes()?.map(MemoryLimit::from_bytes),
+            application_limits.fds,
+        ))
+    }
+
+    /// Measures the usage of `HOME`, `TMPDIR` and `PWD` against `limits.disk` of `VCAP_APPLICATION`, see [`crate::get_disk_usage`]
+    pub fn get_disk_usage(&self) -> Result<DiskUsage, Error> {
+        let directories = [
+            (HOME, self.get_home()),
+            (TMPDIR, self.get_tmp_dir()),
+            (PWD, self.get_pwd()),
+        ]
+        .into_iter()
+        .filter_map(|(variable_name, directory)| Some((variable_name, directory.ok()?)))
+        .collect::<Vec<_>>();
+        let limit = match self.get_application_lenient() {
+            Ok(application) => application.limits.unwrap_or_default().disk_bytes()?,
+            Err(Error::EnvNotSet(_)) => None,
+            Err(e) => return Err(e),
+        };
+
+        DiskUsage::measure(&directories, limit)
+    }
+
+    /// Get's the value from `PORT` as a typed 16
+    pub fn get_port(&self) -> Result<u16, Error> {
+        match self.var(PORT)?.parse::<u16>() {
+            Ok(result) => Ok(result),
+            Err(_) => Err(Error::EnvMalformed(
+                PORT.to_string(),
+                "Ins't a valid positive (u16) number".to_string(),
+            )),
+        }
+    }
+
+    /// Get's the value from `PWD` as a typed PathBuf
+    pub fn get_pwd(&self) -> Result<PathBuf, Error> {
+        Ok(PathBuf::from(self.var(PWD)?))
+    }
+
+    /// Get's the value from `TMPDIR` as a typed PathBuf
+    pub fn get_tmp_dir(&self) -> Result<PathBuf, Error> {
+        Ok(PathBuf::from(self.var(TMPDIR)?))
+    }
+
+    /// Get's the value from `USER`
+    pub fn get_user(&self) -> Result<String, Error> {
+        self.var(USER)
+    }
+
+    fn parse_services(&self) -> Result<(ServiceMap, ServicesSource, Vec<ServiceWarning>), Error> {
+        let services_source = self.get_services_source()?;
+        let services = match &services_source {
+            ServicesSource::File(path) => match fs::read_to_string(path) {
+                Ok(services) => services,
+                Err(err) => {
+                    return Err(Error::FileUnreadable(
+                        VCAP_SERVICES_FILE_PATH.to_string(),
+                        FileError::new(path, err),
+                    ))
+                }
+            },
+            ServicesSource::Env => self.var(VCAP_SERVICES)?,
+            ServicesSource::BindingRoot(path) => {
+                return Ok((read_binding_root(path)?, services_source, Vec::new()))
+            }
+        };
+
+        let parsed = if self.lenient {
+            parse_services_lenient(&services)
+        } else {
+            parse_json::<ServiceMap>(&services).map(|value| (value, Vec::new()))
+        };
+        match parsed {
+            Ok((value, warnings)) => Ok((value, services_source, warnings)),
+            Err(err) => Err(Error::JsonMalformed(
+                services_source.variable_name().to_string(),
+                err,
+            )),
+        }
+    }
+
+    /// Get's where the services are read from
+    ///
+    /// In order of precedence the file at `VCAP_SERVICES_FILE_PATH`, `VCAP_SERVICES` or the servicebinding.io bindings at `SERVICE_BINDING_ROOT`. If the environment was loaded it's where they were read from at that time.
+    pub fn get_services_source(&self) -> Result<ServicesSource, Error> {
+        if let Some(cache) = &self.cache {
+            return match &cache.services_source {
+                Some(services_source) => Ok(services_source.clone()),
+                None => Err(Error::EnvNotSet(VCAP_SERVICES.to_string())),
+            };
+        }
+
+        match (
+            self.source.var(VCAP_SERVICES_FILE_PATH),
+            self.source.var(VCAP_SERVICES),
+            self.source.var(SERVICE_BINDING_ROOT),
+        ) {
+            (Some(path), _, _) => Ok(ServicesSource::File(PathBuf::from(path))),
+            (None, Some(_), _) => Ok(ServicesSource::Env),
+            (None, None, Some(root)) => Ok(ServicesSource::BindingRoot(PathBuf::from(root))),
+            (None, None, None) => Err(Error::EnvNotSet(VCAP_SERVICES.to_string())),
+        }
+    }
+
+    fn parse_application_info(&self) -> Result<Application, Error> {
+        match parse_json::<Application>(&self.var(VCAP_APPLICATION)?) {
+            Ok(value) => Ok(value),
+            Err(err) => Err(Error::JsonMalformed(VCAP_APPLICATION.to_string(), err)),
+        }
+    }
+
+    fn parse_application_lenient(&self) -> Result<LenientApplication, Error> {
+        match LenientApplication::from_json(&self.var(VCAP_APPLICATION)?) {
+            Ok(value) => Ok(value),
+            Err(err) => Err(Error::JsonMalformed(VCAP_APPLICATION.to_string(), err)),
+        }
+    }
+
+    /// Get's the services from `VCAP_SERVICES` with lookups by name, type, label, tag and instance name
+    ///
+    /// Borrowed if the environment was loaded, parsed on the spot otherwise.
+    pub fn service_index(&self) -> Result<Cow<'_, ServiceIndex>, Error> {
+        match &self.cache {
+            Some(Cache {
+                services: Some(services),
+                ..
+            }) => Ok(Cow::Borrowed(services)),
+            Some(_) => Err(Error::EnvNotSet(VCAP_SERVICES.to_string())),
+            None => Ok(Cow::Owned(ServiceIndex::new(self.parse_services()?.0))),
+        }
+    }
+
+    /// Get's the services from the file at `VCAP_SERVICES_FILE_PATH` or `VCAP_SERVICES` as a typed HashMap of Strings and a list of Services
+    pub fn get_services(&self) -> Result<ServiceMap, Error> {
+        match &self.cache {
+            Some(_) => Ok(self.service_index()?.to_map()),
+            None => Ok(self.parse_services()?.0),
+        }
+    }
+
+    /// Get's the services together with what lenient parsing had to work around, always without warnings in strict mode
+    pub fn get_services_with_warnings(&self) -> Result<(ServiceMap, Vec<ServiceWarning>), Error> {
+        match &self.cache {
+            Some(cache) => Ok((
+                self.service_index()?.to_map(),
+                cache.service_warnings.clone(),
+            )),
+            None => {
+                let (services, _, warnings) = self.parse_services()?;
+                Ok((services, warnings))
+            }
+        }
+    }
+
+    /// Get's you a single service from `VCAP_SERVICES` by it's name, see [`crate::get_service_by_name`]
+    pub fn get_service_by_name<T>(&self, name: &str) -> Result<Service<T>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        match self.service_index()?.by_name(name) {
+            Some(service) => match service.with_credentials::<T>() {
+                Ok(service) => Ok(service),
+                Err(err) => Err(Error::JsonMalformed(
+                    format!("{}.credentials", service.name),
+                    err,
+                )),
+            },
+            None => Err(Error::ServiceNotPresent(name.to_string())),
+        }
+    }
+
+    /// Get's you a list of services from `VCAP_SERVICES` by their type, see [`crate::get_services_by_type`]
+    pub fn get_services_by_type<T>(&self, type_name: &str) -> Result<Vec<Service<T>>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        match self.service_index()?.by_type(type_name) {
+            Some(services) => match services
+                .into_iter()
+                .map(|service| service.with_credentials::<T>())
+                .collect()
+            {
+                Ok(services) => Ok(services),
+                Err(err) => Err(Error::JsonMalformed(
+                    format!("<{type_name}>.credentials"),
+                    err,
+                )),
+            },
+            None => Err(Error::ServiceTypeNotPresent(type_name.to_string())),
+        }
+    }
+
+    /// Get's you all services matching the query, see [`crate::find_services`]
+    pub fn find_services<T>(&self, query: &ServiceQuery) -> Result<Vec<Service<T>>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        query.all_typed(&*self.service_index()?)
+    }
+
+    /// Get's you the only service matching the query, see [`crate::find_service`]
+    pub fn find_service<T>(&self, query: &ServiceQuery) -> Result<Service<T>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        query.one_typed(&*self.service_index()?)
+    }
+
+    /// Get's the platform endpoints from `VCAP_PLATFORM_OPTIONS` as typed PlatformOptions
+    pub fn get_platform_options(&self) -> Result<PlatformOptions, Error> {
+        match parse_json::<PlatformOptions>(&self.var(VCAP_PLATFORM_OPTIONS)?) {
+            Ok(value) => Ok(value),
+            Err(err) => Err(Error::JsonMalformed(VCAP_PLATFORM_OPTIONS.to_string(), err)),
+        }
+    }
+
+    /// Get's you the information from `VCAP_APPLICATION` as a typed Application
+    pub fn get_application_info(&self) -> Result<Application, Error> {
+        match &self.cache {
+            Some(Cache {
+                application_info: Some(application_info),
+                ..
+            }) => Ok(application_info.clone()),
+            // Converted again only to report what's missing
+            Some(Cache {
+                application: Some(application),
+                ..
+            }) => application.to_application(),
+            Some(_) => Err(Error::EnvNotSet(VCAP_APPLICATION.to_string())),
+            None => self.parse_application_info(),
+        }
+    }
+
+    /// Get's you the information from `VCAP_APPLICATION` with every field optional, see [`crate::get_application_lenient`]
+    pub fn get_application_lenient(&self) -> Result<LenientApplication, Error> {
+        match &self.cache {
+            Some(Cache {
+                application: Some(application),
+                ..
+            }) => Ok(application.clone()),
+            Some(_) => Err(Error::EnvNotSet(VCAP_APPLICATION.to_string())),
+            None => self.parse_application_lenient(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::CfEnv;
+    use std::collections::HashMap;
+
+    const APP_DATA: &str = r#"
+    {
+        "cf_api": "https://api.example.io",
+        "limits": { "fds": 12384, "mem": 512, "disk": 1024 },
+        "application_name": "my-backend",
+        "application_uris": ["backend.example.io"],
+        "name": "my-backend",
+        "space_name": "production",
+        "space_id": "29349a46-ff0c-447e-bde0-db1be814f564",
+        "organization_id": "26421037-ab23-4b51-99f8-9f5a6251fd26",
+        "organization_name": "Project_One",
+        "uris": ["backend.example.io"],
+        "process_id": "d8304a62-2df7-41d5-9211-0917c2253591",
+        "process_type": "web",
+        "application_id": "d8304a62-2df7-41d5-9211-0917c2253591",
+        "version": "9fe9fe07-c7b7-415b-afa3-75fef5258d47",
+        "application_version": "9fe9fe07-c7b7-415b-afa3-75fef5258d47"
+    }"#;
+
+    const SERVICE_DATA: &str = r#"
+    {
+        "mongodb": [
+          {
+            "label": "mongodb",
+            "provider": null,
+            "plan": "huge",
+            "name": "my-db",
+            "tags": ["mongodb", "mongo"],
+            "instance_guid": "2b6e08f9-3174-46ff-999d-183dc4c4964d",
+            "instance_name": "lpn-db",
+            "binding_guid": "3290823d-ab9f-4d72-b414-2438144ea9dc",
+            "binding_name": null,
+            "credentials": {
+              "host": "b4386ed6-2770-444e-9a5d-727855f758fa.services.intern",
+              "port": "27801"
+            },
+            "syslog_drain_url": null,
+            "volume_mounts": []
+          }
+        ]
+    }"#;
+
+    fn env(vars: &[(&'static str, &'static str)]) -> CfEnv<HashMap<&'static str, &'static str>> {
+        CfEnv::new(vars.iter().copied().collect())
+    }
+
+    #[test]
+    fn getters_read_from_source() {
+        let env = env(&[
+            ("PORT", "8080"),
+            ("CF_INSTANCE_ADDR", "10.24.8.2:61001"),
+            ("CF_INSTANCE_INDEX", "3"),
+            ("HOME", "/home/vcap"),
+            ("MEMORY_LIMIT", "512M"),
+        ]);
+
+        assert_eq!(env.get_port().unwrap(), 8080);
+        assert_eq!(env.get_instance_address().unwrap().port(), 61001);
+        assert_eq!(env.get_instance_index().unwrap(), 3);
+        assert_eq!(env.get_home().unwrap().to_str().unwrap(), "/home/vcap");
+        assert_eq!(env.get_memory_limit().unwrap().size, 512);
+    }
+
+    #[test]
+    fn getters_not_set() {
+        let env = env(&[]);
+
+        assert!(!env.is_cf_env());
+        assert_eq!(
+            env.get_port().unwrap_err(),
+            crate::Error::EnvNotSet(crate::PORT.to_string())
+        );
+        assert_eq!(
+            env.get_services().unwrap_err(),
+            crate::Error::EnvNotSet(crate::VCAP_SERVICES.to_string())
+        );
+    }
+
+    #[test]
+    fn getters_malformed() {
+        let env = env(&[("PORT", "port"), ("LANG", "hello")]);
+
+        assert!(matches!(
+            env.get_port(),
+            Err(crate::Error::EnvMalformed(..))
+        ));
+        assert!(matches!(
+            env.get_lang(),
+            Err(crate::Error::EnvMalformed(..))
+        ));
+    }
+
+    #[test]
+    fn platform_options() {
+        let env = env(&[(
+            "VCAP_PLATFORM_OPTIONS",
+            r#"{ "credhub-uri": "https://credhub.service.cf.internal:8844", "other-uri": "https://other.example.io" }"#,
+        )]);
+
+        let options = env.get_platform_options().unwrap();
+        assert_eq!(
+            options.credhub_uri.as_deref(),
+            Some("https://credhub.service.cf.internal:8844")
+        );
+        assert_eq!(options.extra["other-uri"], "https://other.example.io");
+        assert_eq!(
+            serde_json::to_value(&options).unwrap()["credhub-uri"],
+            "https://credhub.service.cf.internal:8844"
+        );
+
+        assert_eq!(
+            self::env(&[]).get_platform_options().unwrap_err(),
+            crate::Error::EnvNotSet(crate::VCAP_PLATFORM_OPTIONS.to_string())
+        );
+        assert_eq!(
+            self::env(&[("VCAP_PLATFORM_OPTIONS", r#"{ "credhub-uri": 8844 }"#)])
+                .get_platform_options()
+                .unwrap_err()
+                .kind(),
+            crate::ErrorKind::JsonMalformed
+        );
+    }
+
+    #[test]
+    fn services_from_source() {
+        let env = env(&[("VCAP_SERVICES", SERVICE_DATA)]);
+
+        let service = env
+            .get_service_by_name::<serde_json::Value>("my-db")
+            .unwrap();
+        assert_eq!(service.label, "mongodb");
+
+        let services = env
+            .get_services_by_type::<serde_json::Value>("mongodb")
+            .unwrap();
+        assert_eq!(services.len(), 1);
+
+        assert_eq!(
+            env.get_service_by_name::<serde_json::Value>("the-db")
+                .unwrap_err(),
+            crate::Error::ServiceNotPresent("the-db".to_string())
+        );
+        assert_eq!(
+            env.get_services_by_type::<serde_json::Value>("redis")
+                .unwrap_err(),
+            crate::Error::ServiceTypeNotPresent("redis".to_string())
+        );
+    }
+
+    #[test]
+    fn load_parses_once() {
+        let env = CfEnv::load_from(HashMap::from([
+            ("VCAP_SERVICES", SERVICE_DATA),
+            ("VCAP_APPLICATION", APP_DATA),
+        ]))
+        .unwrap();
+        assert!(env.is_loaded());
+
+        let service = env
+            .get_service_by_name::<serde_json::Value>("my-db")
+            .unwrap();
+        assert_eq!(service.credentials["port"], "27801");
+        assert_eq!(env.service_index().unwrap().by_tag("mongo").len(), 1);
+        assert_eq!(
+            env.get_services().unwrap(),
+            CfEnv::new(HashMap::from([("VCAP_SERVICES", SERVICE_DATA)]))
+                .get_services()
+                .unwrap()
+        );
+        assert_eq!(env.get_application_info().unwrap().name, "my-backend");
+    }
+
+    #[test]
+    fn load_not_set() {
+        let env = CfEnv::load_from(HashMap::<String, String>::new()).unwrap();
+
+        assert_eq!(
+            env.get_services().unwrap_err(),
+            crate::Error::EnvNotSet(crate::VCAP_SERVICES.to_string())
+        );
+        assert_eq!(
+            env.get_application_info().unwrap_err(),
+            crate::Error::EnvNotSet(crate::VCAP_APPLICATION.to_string())
+        );
+    }
+
+    #[test]
+    fn load_malformed() {
+        let error = CfEnv::load_from(HashMap::from([("VCAP_SERVICES", "{")])).unwrap_err();
+
+        assert_eq!(error.kind(), crate::ErrorKind::JsonMalformed);
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "the json from {:?} could not be parsed",
+                crate::VCAP_SERVICES
+            )
+        );
+    }
+
+    #[test]
+    fn load_typed_credentials() {
+        #[derive(serde::Deserialize)]
+        struct Credentials {
+            host: String,
+        }
+        #[derive(serde::Deserialize, Debug)]
+        struct WrongCredentials {
+            #[allow(dead_code)]
+            not_available: String,
+        }
+
+        let env = CfEnv::load_from(HashMap::from([("VCAP_SERVICES", SERVICE_DATA)])).unwrap();
+
+        let services = env.get_services_by_type::<Credentials>("mongodb").unwrap();
+        assert!(services[0].credentials.host.ends_with(".services.intern"));
+        match env.get_service_by_name::<WrongCredentials>("my-db") {
+            Err(crate::Error::JsonMalformed(name, source)) => {
+                assert_eq!(name, "my-db.credentials");
+                assert_eq!(source.path(), ".");
+                assert!(source.to_string().contains("not_available"));
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+    }
+
+    #[test]
+    fn instance_ports() {
+        let env = env(&[(
+            "CF_INSTANCE_PORTS",
+            r#"[
+                {"external": 61001, "internal": 8080, "external_tls_proxy": 61002, "internal_tls_proxy": 61003},
+                {"external": 61004, "internal": 2222}
+            ]"#,
+        )]);
+
+        let ports = env.get_instance_ports().unwrap();
+        assert_eq!(ports.iter().count(), 2);
+        assert_eq!(
+            ports.by_internal(8080).unwrap().internal_tls_proxy,
+            Some(61003)
+        );
+        assert_eq!(ports.external_for(8080), Some(61002));
+        assert_eq!(ports.external_for(2222), Some(61004));
+        assert!(ports.by_internal(9090).is_none());
+    }
+
+    #[test]
+    fn instance_ports_malformed() {
+        let env = env(&[("CF_INSTANCE_PORTS", r#"[{"external": 61001}]"#)]);
+
+        match env.get_instance_ports() {
+            Err(crate::Error::JsonMalformed(name, source)) => {
+                assert_eq!(name, crate::CF_INSTANCE_PORTS);
+                assert_eq!(source.path(), "[0]");
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+        assert_eq!(
+            CfEnv::new(HashMap::<String, String>::new())
+                .get_instance_ports()
+                .unwrap_err(),
+            crate::Error::EnvNotSet(crate::CF_INSTANCE_PORTS.to_string())
+        );
+    }
+
+    #[test]
+    fn services_from_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("vcap-services.json");
+        std::fs::write(&path, SERVICE_DATA).unwrap();
+
+        let env = CfEnv::new(HashMap::from([
+            ("VCAP_SERVICES_FILE_PATH", path.to_str().unwrap()),
+            ("VCAP_SERVICES", "{}"),
+        ]));
+
+        assert_eq!(
+            env.get_services_source().unwrap(),
+            crate::ServicesSource::File(path.clone())
+        );
+        assert_eq!(
+            env.get_service_by_name::<serde_json::Value>("my-db")
+                .unwrap()
+                .label,
+            "mongodb"
+        );
+
+        let loaded = CfEnv::load_from(env.source().clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.get_services().unwrap().len(), 1);
+        assert_eq!(
+            loaded.get_services_source().unwrap(),
+            crate::ServicesSource::File(path)
+        );
+    }
+
+    #[test]
+    fn services_file_errors() {
+        let env = env(&[("VCAP_SERVICES_FILE_PATH", "/does/not/exist.json")]);
+        assert_eq!(
+            env.get_services().unwrap_err().kind(),
+            crate::ErrorKind::FileUnreadable
+        );
+
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("vcap-services.json");
+        std::fs::write(&path, "{").unwrap();
+        let env = CfEnv::new(HashMap::from([(
+            "VCAP_SERVICES_FILE_PATH",
+            path.to_str().unwrap(),
+        )]));
+        match env.get_services() {
+            Err(crate::Error::JsonMalformed(name, _)) => {
+                assert_eq!(name, crate::VCAP_SERVICES_FILE_PATH)
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+    }
+
+    #[test]
+    fn services_from_env_fallback() {
+        let env = env(&[("VCAP_SERVICES", SERVICE_DATA)]);
+
+        assert_eq!(
+            env.get_services_source().unwrap(),
+            crate::ServicesSource::Env
+        );
+        assert_eq!(
+            self::env(&[]).get_services_source().unwrap_err(),
+            crate::Error::EnvNotSet(crate::VCAP_SERVICES.to_string())
+        );
+    }
+}
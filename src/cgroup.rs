@@ -0,0 +1,325 @@
+//! The limits the container actually enforces, read from cgroup v1 or v2 and `/proc/self/limits`
+//!
+//! Values which can't be read or are unlimited are `None`, so this works the same inside and outside of containers.
+use crate::models::MemoryLimit;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::thread;
+
+const CGROUP_ROOT: &str = "sys/fs/cgroup";
+const PROC_LIMITS: &str = "proc/self/limits";
+/// cgroup v1 reports no limit as a huge page aligned number instead
+const V1_UNLIMITED: u128 = 1 << 62;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum CgroupVersion {
+    V1,
+    V2,
+}
+
+/// The limits of the container, see [`crate::cgroup`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ContainerLimits {
+    pub cgroup_version: Option<CgroupVersion>,
+    pub memory: Option<MemoryLimit>,
+    /// The cpu quota in cpus, e.g. `1.5` for 150ms every 100ms
+    pub cpu_quota: Option<f64>,
+    /// The relative cpu weight of cgroup v1 as is
+    ///
+    /// Garden sets it in proportion to the memory limit, so it only ranks containers against each other and isn't a cpu count.
+    pub cpu_shares: Option<u64>,
+    /// The relative cpu weight of cgroup v2 as is, 100 by default, with the same caveat as `cpu_shares`
+    pub cpu_weight: Option<u64>,
+    /// The soft limit of open files, `RLIMIT_NOFILE`
+    pub open_files: Option<u128>,
+}
+
+impl ContainerLimits {
+    /// Reads the limits of the current container
+    pub fn read() -> Self {
+        Self::read_from("/")
+    }
+
+    /// Reads the limits from below `root` instead of `/`, e.g. from a fixture tree
+    pub fn read_from(root: impl AsRef<Path>) -> Self {
+        let root = root.as_ref();
+        let cgroup = root.join(CGROUP_ROOT);
+
+        let mut limits = if cgroup.join("cgroup.controllers").is_file() {
+            Self {
+                cgroup_version: Some(CgroupVersion::V2),
+                memory: read_value(&cgroup.join("memory.max")).map(MemoryLimit::from_bytes),
+                cpu_quota: read_string(&cgroup.join("cpu.max")).and_then(|max| {
+                    let (quota, period) = max.split_once(' ')?;
+                    quota_in_cpus(quota.parse().ok()?, period.parse().ok()?)
+                }),
+                cpu_shares: None,
+                cpu_weight: read_value(&cgroup.join("cpu.weight")).map(|weight| weight as u64),
+                open_files: None,
+            }
+        } else if cgroup.join("memory").is_dir() || cgroup.join("cpu").is_dir() {
+            let cpu = ["cpu", "cpu,cpuacct"]
+                .iter()
+                .map(|controller| cgroup.join(controller))
+                .find(|controller| controller.is_dir())
+                .unwrap_or_else(|| cgroup.join("cpu"));
+            Self {
+                cgroup_version: Some(CgroupVersion::V1),
+                memory: read_value(&cgroup.join("memory").join("memory.limit_in_bytes"))
+                    .filter(|limit| *limit < V1_UNLIMITED)
+                    .map(MemoryLimit::from_bytes),
+                cpu_quota: read_string(&cpu.join("cpu.cfs_quota_us")).and_then(|quota| {
+                    let period = read_string(&cpu.join("cpu.cfs_period_us"))?;
+                    quota_in_cpus(quota.parse().ok()?, period.parse().ok()?)
+                }),
+                cpu_shares: read_value(&cpu.join("cpu.shares")).map(|shares| shares as u64),
+                cpu_weight: None,
+                open_files: None,
+            }
+        } else {
+            Self::default()
+        };
+
+        limits.open_files = read_string(&root.join(PROC_LIMITS)).and_then(|proc_limits| {
+            proc_limits
+                .lines()
+                .find_map(|line| line.strip_prefix("Max open files"))
+                .and_then(|values| values.split_whitespace().next()?.parse().ok())
+        });
+        limits
+    }
+
+    /// The cpus the container may use, only known if a quota is set as shares and weights aren't cpu counts
+    pub fn effective_cpus(&self) -> Option<f64> {
+        self.cpu_quota
+    }
+
+    /// The number of worker threads to run, e.g. for tokio
+    ///
+    /// The effective cpus rounded up, but at least one and at most the available parallelism of the host. Without a quota it's the available parallelism.
+    pub fn worker_threads(&self) -> usize {
+        let available = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        match self.effective_cpus() {
+            Some(cpus) => (cpus.ceil() as usize).clamp(1, available),
+            None => available,
+        }
+    }
+
+    /// Compares the limits declared by Cloud Foundry with the ones enforced
+    ///
+    /// `memory_limit` is the value of `MEMORY_LIMIT`, `declared_memory` and `declared_open_files` are `limits.mem` and `limits.fds` of `VCAP_APPLICATION`, see [`crate::LenientApplicationLimits::mem_bytes`]. Not declared limits aren't compared.
+    pub fn compare(
+        &self,
+        memory_limit: Option<MemoryLimit>,
+        declared_memory: Option<MemoryLimit>,
+        declared_open_files: Option<u128>,
+    ) -> Vec<LimitMismatch> {
+        let actual_memory = self.memory.map(|memory| memory.as_bytes());
+        let declared = [
+            (
+                LimitSource::MemoryLimit,
+                memory_limit.map(|limit| limit.as_bytes()),
+                actual_memory,
+            ),
+            (
+                LimitSource::ApplicationMemory,
+                declared_memory.map(|limit| limit.as_bytes()),
+                actual_memory,
+            ),
+            (
+                LimitSource::ApplicationFds,
+                declared_open_files,
+                self.open_files,
+            ),
+        ];
+
+        declared
+            .into_iter()
+            .filter_map(|(source, declared, actual)| match declared {
+                Some(declared) if actual != Some(declared) => Some(LimitMismatch {
+                    source,
+                    declared,
+                    actual,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Where a declared limit comes from
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum LimitSource {
+    /// `MEMORY_LIMIT`, compared with the cgroup memory limit
+    MemoryLimit,
+    /// `limits.mem` of `VCAP_APPLICATION`, compared with the cgroup memory limit
+    ApplicationMemory,
+    /// `limits.fds` of `VCAP_APPLICATION`, compared with `RLIMIT_NOFILE`
+    ApplicationFds,
+}
+
+/// A declared limit which isn't the one enforced, in bytes for memory and as count for open files
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct LimitMismatch {
+    pub source: LimitSource,
+    pub declared: u128,
+    /// `None` if there is no limit enforced
+    pub actual: Option<u128>,
+}
+
+impl Display for LimitMismatch {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        let name = match self.source {
+            LimitSource::MemoryLimit => "MEMORY_LIMIT",
+            LimitSource::ApplicationMemory => "limits.mem",
+            LimitSource::ApplicationFds => "limits.fds",
+        };
+        match self.actual {
+            Some(actual) => write!(
+                formatter,
+                "{name} declares {} but the container enforces {actual}",
+                self.declared
+            ),
+            None => write!(
+                formatter,
+                "{name} declares {} but the container enforces no limit",
+                self.declared
+            ),
+        }
+    }
+}
+
+fn read_string(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|content| content.trim().to_string())
+}
+
+/// Reads a number, `None` for `max` and `-1` which both mean unlimited
+fn read_value(path: &Path) -> Option<u128> {
+    read_string(path)?.parse().ok()
+}
+
+fn quota_in_cpus(quota: u128, period: u128) -> Option<f64> {
+    match period {
+        0 => None,
+        _ => Some(quota as f64 / period as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CfEnv, CgroupVersion, ContainerLimits, LimitSource, MemoryLimit};
+    use std::collections::HashMap;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+    const APP_DATA: &str = r#"{ "limits": { "fds": 16384, "mem": 1024, "disk": 1024 } }"#;
+
+    #[test]
+    fn reads_v1() {
+        let limits = ContainerLimits::read_from(format!("{FIXTURES}/cgroup-v1"));
+
+        assert_eq!(limits.cgroup_version, Some(CgroupVersion::V1));
+        assert_eq!(limits.memory, Some("1G".parse().unwrap()));
+        assert_eq!(limits.cpu_quota, Some(2.0));
+        assert_eq!(limits.cpu_shares, Some(512));
+        assert_eq!(limits.cpu_weight, None);
+        assert_eq!(limits.open_files, Some(16384));
+        assert_eq!(limits.effective_cpus(), Some(2.0));
+    }
+
+    #[test]
+    fn reads_v2() {
+        let limits = ContainerLimits::read_from(format!("{FIXTURES}/cgroup-v2"));
+
+        assert_eq!(limits.cgroup_version, Some(CgroupVersion::V2));
+        assert_eq!(limits.memory, Some("512M".parse().unwrap()));
+        assert_eq!(limits.cpu_quota, None);
+        assert_eq!(limits.cpu_shares, None);
+        assert_eq!(limits.cpu_weight, Some(100));
+        assert_eq!(limits.open_files, Some(1024));
+        assert_eq!(limits.effective_cpus(), None);
+        assert_eq!(
+            limits.worker_threads(),
+            std::thread::available_parallelism().unwrap().get()
+        );
+    }
+
+    #[test]
+    fn no_cgroups() {
+        let limits = ContainerLimits::read_from(format!("{FIXTURES}/does-not-exist"));
+
+        assert_eq!(limits, ContainerLimits::default());
+        assert_eq!(
+            limits.worker_threads(),
+            std::thread::available_parallelism().unwrap().get()
+        );
+    }
+
+    #[test]
+    fn matching_limits() {
+        let env = CfEnv::new(HashMap::from([
+            ("MEMORY_LIMIT", "1024M"),
+            ("VCAP_APPLICATION", APP_DATA),
+        ]));
+
+        let limits = ContainerLimits::read_from(format!("{FIXTURES}/cgroup-v1"));
+        assert!(env.check_container_limits(&limits).unwrap().is_empty());
+    }
+
+    #[test]
+    fn mismatching_limits() {
+        let env = CfEnv::new(HashMap::from([
+            ("MEMORY_LIMIT", "1G"),
+            ("VCAP_APPLICATION", APP_DATA),
+        ]));
+
+        let mismatches = env
+            .check_container_limits(&ContainerLimits::read_from(format!("{FIXTURES}/cgroup-v2")))
+            .unwrap();
+        let sources = mismatches
+            .iter()
+            .map(|mismatch| mismatch.source)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sources,
+            vec![
+                LimitSource::MemoryLimit,
+                LimitSource::ApplicationMemory,
+                LimitSource::ApplicationFds
+            ]
+        );
+        assert_eq!(mismatches[0].actual, Some(512 << 20));
+        assert_eq!(
+            mismatches[2].to_string(),
+            "limits.fds declares 16384 but the container enforces 1024"
+        );
+
+        let unlimited =
+            ContainerLimits::default().compare(Some(MemoryLimit::from_bytes(1 << 30)), None, None);
+        assert_eq!(unlimited[0].actual, None);
+    }
+
+    #[test]
+    fn overflowing_declared_memory() {
+        let env = CfEnv::new(HashMap::from([(
+            "VCAP_APPLICATION",
+            r#"{ "limits": { "mem": 340282366920938463463374607431768211455 } }"#,
+        )]));
+
+        assert_eq!(
+            env.check_container_limits(&ContainerLimits::read_from(format!(
+                "{FIXTURES}/cgroup-v1"
+            )))
+            .unwrap_err(),
+            crate::Error::EnvMalformed(
+                "VCAP_APPLICATION".to_string(),
+                "limits.mem is too large to be a number of bytes".to_string()
+            )
+        );
+    }
+}
@@ -1,17 +1,28 @@
 pub const CF_INSTANCE_ADDR: &str = "CF_INSTANCE_ADDR";
+pub const CF_INSTANCE_CERT: &str = "CF_INSTANCE_CERT";
 pub const CF_INSTANCE_GUID: &str = "CF_INSTANCE_GUID";
 pub const CF_INSTANCE_INDEX: &str = "CF_INSTANCE_INDEX";
 pub const CF_INSTANCE_IP: &str = "CF_INSTANCE_IP";
 pub const CF_INSTANCE_INTERNAL_IP: &str = "CF_INSTANCE_INTERNAL_IP";
+pub const CF_INSTANCE_KEY: &str = "CF_INSTANCE_KEY";
 pub const CF_INSTANCE_PORT: &str = "CF_INSTANCE_PORT";
 pub const CF_INSTANCE_PORTS: &str = "CF_INSTANCE_PORTS";
+pub const CF_SYSTEM_CERT_PATH: &str = "CF_SYSTEM_CERT_PATH";
 pub const DATABASE_URL: &str = "DATABASE_URL";
 pub const HOME: &str = "HOME";
 pub const LANG: &str = "LANG";
+pub const LC_ALL: &str = "LC_ALL";
+pub const LC_MESSAGES: &str = "LC_MESSAGES";
 pub const MEMORY_LIMIT: &str = "MEMORY_LIMIT";
 pub const PORT: &str = "PORT";
 pub const PWD: &str = "PWD";
+pub const SERVICE_BINDING_ROOT: &str = "SERVICE_BINDING_ROOT";
 pub const TMPDIR: &str = "TMPDIR";
 pub const USER: &str = "USER";
 pub const VCAP_APPLICATION: &str = "VCAP_APPLICATION";
+pub const VCAP_PLATFORM_OPTIONS: &str = "VCAP_PLATFORM_OPTIONS";
 pub const VCAP_SERVICES: &str = "VCAP_SERVICES";
+pub const VCAP_SERVICES_FILE_PATH: &str = "VCAP_SERVICES_FILE_PATH";
+
+/// Where Cloud Foundry puts the platform CA certificates if `CF_SYSTEM_CERT_PATH` isn't set
+pub const DEFAULT_SYSTEM_CERT_PATH: &str = "/etc/cf-system-certificates";
@@ -0,0 +1,204 @@
+use crate::enums::{Error, FileError};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Disk usage of the writable directories of the app against the disk quota, see [`crate::get_disk_usage`]
+///
+/// Diego evicts the container once the quota of `limits.disk` is used up, caches can check [`DiskUsage::exceeds`] to clean up before that.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DiskUsage {
+    /// The measured directories with their usage in bytes, nested directories are counted with the outer one
+    pub directories: Vec<(PathBuf, u128)>,
+    /// The quota in bytes
+    pub limit: Option<u128>,
+}
+
+impl DiskUsage {
+    /// Measures the usage of `directories` against `limit` bytes
+    ///
+    /// Directories within another given one are skipped, symlinks aren't followed and entries which can't be read don't count.
+    pub fn measure(
+        directories: &[(&'static str, PathBuf)],
+        limit: Option<u128>,
+    ) -> Result<Self, Error> {
+        let mut measured: Vec<(PathBuf, u128)> = Vec::new();
+
+        for (variable_name, directory) in directories {
+            let nested = directories
+                .iter()
+                .any(|(_, other)| other != directory && directory.starts_with(other));
+            if nested || measured.iter().any(|(path, _)| path == directory) {
+                continue;
+            }
+
+            match directory_size(directory) {
+                Ok(size) => measured.push((directory.clone(), size)),
+                Err(err) => {
+                    return Err(Error::FileUnreadable(
+                        variable_name.to_string(),
+                        FileError::new(directory, err),
+                    ))
+                }
+            }
+        }
+
+        Ok(Self {
+            directories: measured,
+            limit,
+        })
+    }
+
+    /// The used bytes of all directories
+    pub fn used(&self) -> u128 {
+        self.directories.iter().map(|(_, size)| size).sum()
+    }
+
+    /// The bytes left until the quota is reached
+    pub fn remaining(&self) -> Option<u128> {
+        Some(self.limit?.saturating_sub(self.used()))
+    }
+
+    /// The used share of the quota in percent, might be over 100
+    pub fn percentage(&self) -> Option<f64> {
+        match self.limit? {
+            0 => None,
+            limit => Some(self.used() as f64 * 100.0 / limit as f64),
+        }
+    }
+
+    /// Whether more than `percent` of the quota are used, `false` if there is no quota
+    pub fn exceeds(&self, percent: f64) -> bool {
+        self.percentage()
+            .is_some_and(|percentage| percentage > percent)
+    }
+}
+
+/// The allocated size of all files below `path`
+fn directory_size(path: &Path) -> io::Result<u128> {
+    let mut size = 0;
+    let mut pending = vec![fs::read_dir(path)?];
+
+    while let Some(entries) = pending.last_mut() {
+        let Some(entry) = entries.next() else {
+            pending.pop();
+            continue;
+        };
+        let Ok(metadata) = entry.and_then(|entry| {
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                pending.push(fs::read_dir(entry.path())?);
+            }
+            Ok(metadata)
+        }) else {
+            continue;
+        };
+        size += allocated_size(&metadata);
+    }
+    Ok(size)
+}
+
+/// Counts blocks on unix, as the quota does
+#[cfg(unix)]
+fn allocated_size(metadata: &fs::Metadata) -> u128 {
+    use std::os::unix::fs::MetadataExt;
+    if metadata.is_file() {
+        u128::from(metadata.blocks()) * 512
+    } else {
+        0
+    }
+}
+
+#[cfg(not(unix))]
+fn allocated_size(metadata: &fs::Metadata) -> u128 {
+    if metadata.is_file() {
+        u128::from(metadata.len())
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CfEnv, DiskUsage};
+    use std::collections::HashMap;
+    use std::fs;
+
+    const MEGABYTE: usize = 1 << 20;
+
+    #[test]
+    fn measures_directories() {
+        let home = tempfile::tempdir().unwrap();
+        let app = home.path().join("app");
+        let tmp = home.path().join("tmp");
+        fs::create_dir_all(app.join("nested")).unwrap();
+        fs::create_dir_all(&tmp).unwrap();
+        fs::write(app.join("nested").join("data"), vec![1u8; MEGABYTE]).unwrap();
+        fs::write(tmp.join("cache"), vec![1u8; 2 * MEGABYTE]).unwrap();
+
+        let env = CfEnv::new(HashMap::from([
+            ("HOME", home.path().to_str().unwrap()),
+            ("TMPDIR", tmp.to_str().unwrap()),
+            ("PWD", app.to_str().unwrap()),
+            ("VCAP_APPLICATION", r#"{ "limits": { "disk": 4 } }"#),
+        ]));
+
+        let usage = env.get_disk_usage().unwrap();
+        assert_eq!(usage.directories.len(), 1);
+        assert_eq!(usage.limit, Some(4 * MEGABYTE as u128));
+        assert!(usage.used() >= 3 * MEGABYTE as u128);
+        assert!(usage.used() < 4 * MEGABYTE as u128);
+        assert!(usage.exceeds(70.0));
+        assert!(!usage.exceeds(99.0));
+        assert!(usage.remaining().unwrap() <= MEGABYTE as u128);
+    }
+
+    #[test]
+    fn separate_directories() {
+        let home = tempfile::tempdir().unwrap();
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("cache"), vec![1u8; MEGABYTE]).unwrap();
+
+        let usage = DiskUsage::measure(
+            &[
+                ("HOME", home.path().to_path_buf()),
+                ("TMPDIR", tmp.path().to_path_buf()),
+                ("PWD", tmp.path().to_path_buf()),
+            ],
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(usage.directories.len(), 2);
+        assert_eq!(usage.directories[0].1, 0);
+        assert_eq!(usage.percentage(), None);
+        assert!(!usage.exceeds(0.0));
+    }
+
+    #[test]
+    fn overflowing_limit() {
+        let env = CfEnv::new(HashMap::from([(
+            "VCAP_APPLICATION",
+            r#"{ "limits": { "disk": 340282366920938463463374607431768211455 } }"#,
+        )]));
+
+        assert_eq!(
+            env.get_disk_usage().unwrap_err(),
+            crate::Error::EnvMalformed(
+                "VCAP_APPLICATION".to_string(),
+                "limits.disk is too large to be a number of bytes".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn missing_directory() {
+        let env = CfEnv::new(HashMap::from([("TMPDIR", "/does/not/exist")]));
+
+        assert_eq!(
+            env.get_disk_usage().unwrap_err().kind(),
+            crate::ErrorKind::FileUnreadable
+        );
+    }
+}
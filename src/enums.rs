@@ -1,17 +1,112 @@
+use crate::models::MemoryLimit;
+use serde::de::DeserializeOwned;
+use serde::ser::{SerializeStruct, Serializer};
 use serde::{Deserialize, Serialize};
+use std::error::Error as StdError;
 use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io;
+use std::path::{Path, PathBuf};
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
-pub enum Error<'a> {
-    EnvNotSet(&'a str),
+/// Errors of this crate
+///
+/// Owned and `'static`, so it can be boxed or put into error chains. Use [`Error::kind`] to match on it in a stable way and [`std::error::Error::source`] to get to the underlying json or io error.
+///
+/// Serializes as its kind and message, e.g. `{"kind":"env_not_set","message":"environment variable \"PORT\" is not set"}`.
+#[derive(PartialEq, Debug)]
+pub enum Error {
+    EnvNotSet(String),
     EnvMalformed(String, String),
-    JsonMalformed(String),
-    ServiceNotPresent(&'a str),
-    ServiceTypeNotPresent(&'a str),
+    JsonMalformed(String, JsonError),
+    FileUnreadable(String, FileError),
+    ServiceNotPresent(String),
+    ServiceTypeNotPresent(String),
+    /// The credentials of the named service lack something or hold something invalid, wherever the service was read from
+    ServiceMalformed(String, String),
+    /// A [`crate::ServiceQuery`] expected to match one service matched all of the named ones
+    ServiceAmbiguous(String, Vec<String>),
+    /// A [`crate::ServiceQuery`] expected to match one service matched none
+    ServiceQueryNoMatch(String),
     UnknownMemoryUnit,
+    /// A [`crate::MemoryBudget`] needs more than the memory limit, holds the limit and what's needed
+    MemoryInsufficient(MemoryLimit, MemoryLimit),
+    /// CredHub couldn't be reached or refused to resolve the credential references, holds the reason
+    CredHubFailed(String),
 }
 
-impl Display for Error<'_> {
+/// Machine readable kind of an [`Error`]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    EnvNotSet,
+    EnvMalformed,
+    JsonMalformed,
+    FileUnreadable,
+    ServiceNotPresent,
+    ServiceTypeNotPresent,
+    ServiceMalformed,
+    ServiceAmbiguous,
+    ServiceQueryNoMatch,
+    UnknownMemoryUnit,
+    MemoryInsufficient,
+    CredHubFailed,
+}
+
+impl ErrorKind {
+    /// Stable snake case identifier, e.g. `env_not_set`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::EnvNotSet => "env_not_set",
+            Self::EnvMalformed => "env_malformed",
+            Self::JsonMalformed => "json_malformed",
+            Self::FileUnreadable => "file_unreadable",
+            Self::ServiceNotPresent => "service_not_present",
+            Self::ServiceTypeNotPresent => "service_type_not_present",
+            Self::ServiceMalformed => "service_malformed",
+            Self::ServiceAmbiguous => "service_ambiguous",
+            Self::ServiceQueryNoMatch => "service_query_no_match",
+            Self::UnknownMemoryUnit => "unknown_memory_unit",
+            Self::MemoryInsufficient => "memory_insufficient",
+            Self::CredHubFailed => "credhub_failed",
+        }
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        formatter.write_str(self.as_str())
+    }
+}
+
+impl Error {
+    /// Reports an [`Error::EnvMalformed`] of a value from the credentials of `service_name` as [`Error::ServiceMalformed`]
+    pub(crate) fn in_service(self, service_name: &str) -> Self {
+        match self {
+            Self::EnvMalformed(_, comment) => {
+                Self::ServiceMalformed(service_name.to_string(), comment)
+            }
+            other => other,
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::EnvNotSet(_) => ErrorKind::EnvNotSet,
+            Self::EnvMalformed(..) => ErrorKind::EnvMalformed,
+            Self::JsonMalformed(..) => ErrorKind::JsonMalformed,
+            Self::FileUnreadable(..) => ErrorKind::FileUnreadable,
+            Self::ServiceNotPresent(_) => ErrorKind::ServiceNotPresent,
+            Self::ServiceTypeNotPresent(_) => ErrorKind::ServiceTypeNotPresent,
+            Self::ServiceMalformed(..) => ErrorKind::ServiceMalformed,
+            Self::ServiceAmbiguous(..) => ErrorKind::ServiceAmbiguous,
+            Self::ServiceQueryNoMatch(_) => ErrorKind::ServiceQueryNoMatch,
+            Self::UnknownMemoryUnit => ErrorKind::UnknownMemoryUnit,
+            Self::MemoryInsufficient(..) => ErrorKind::MemoryInsufficient,
+            Self::CredHubFailed(_) => ErrorKind::CredHubFailed,
+        }
+    }
+}
+
+impl Display for Error {
     fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
         match self {
             Self::EnvNotSet(variable_name) => write!(
@@ -26,39 +121,312 @@ impl Display for Error<'_> {
                 formatter,
                 "service type {service_type_name:?} is not present in VCAP_SERVICES",
             ),
-            Self::JsonMalformed(variable_to_parse_name) => write!(
+            Self::ServiceMalformed(service_name, comment) => write!(
+                formatter,
+                "the credentials of service {service_name:?} do not match the required criteria. {comment:?}",
+            ),
+            Self::ServiceAmbiguous(query, service_names) => write!(
+                formatter,
+                "services {} all match {query:?}, expected exactly one",
+                service_names
+                    .iter()
+                    .map(|service_name| format!("{service_name:?}"))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+            Self::ServiceQueryNoMatch(query) => {
+                write!(formatter, "no service matches {query:?}")
+            }
+            Self::JsonMalformed(variable_to_parse_name, _) => write!(
                 formatter,
                 "the json from {variable_to_parse_name:?} could not be parsed"
             ),
+            Self::FileUnreadable(variable_name, _) => write!(
+                formatter,
+                "the file from {variable_name:?} could not be read"
+            ),
             Self::EnvMalformed(variable_name, comment) => write!(
                 formatter,
                 "the env variable {variable_name:?} does not match the required criterial. {comment:?}",
             ),
             Self::UnknownMemoryUnit => write!(formatter, "memory unit unknown"),
+            Self::MemoryInsufficient(limit, needed) => write!(
+                formatter,
+                "the memory limit of {limit} is too small, the budget needs at least {needed}"
+            ),
+            Self::CredHubFailed(reason) => write!(
+                formatter,
+                "the credential references could not be resolved through CredHub: {reason}"
+            ),
+        }
+    }
+}
+
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Error", 2)?;
+        state.serialize_field("kind", &self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::JsonMalformed(_, source) => Some(source),
+            Self::FileUnreadable(_, source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// A `serde_json` error together with the path in the document it occurred at
+#[derive(Debug)]
+pub struct JsonError {
+    path: String,
+    inner: serde_json::Error,
+}
+
+/// Deserializes `input`, keeping track of the path to report on failure
+pub(crate) fn parse_json<T: DeserializeOwned>(input: &str) -> Result<T, JsonError> {
+    serde_path_to_error::deserialize(&mut serde_json::Deserializer::from_str(input))
+        .map_err(JsonError::from)
+}
+
+/// Deserializes an already parsed `value`, keeping track of the path to report on failure
+pub(crate) fn parse_json_value<T: DeserializeOwned>(
+    value: &serde_json::Value,
+) -> Result<T, JsonError> {
+    serde_path_to_error::deserialize(value).map_err(JsonError::from)
+}
+
+impl JsonError {
+    /// The path to the failing element, e.g. `mongodb[0].credentials.port`, `.` for the root
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// One based line of the error, 0 if it didn't come from parsing a string
+    pub fn line(&self) -> usize {
+        self.inner.line()
+    }
+
+    /// One based column of the error, 0 if it didn't come from parsing a string
+    pub fn column(&self) -> usize {
+        self.inner.column()
+    }
+
+    pub fn inner(&self) -> &serde_json::Error {
+        &self.inner
+    }
+}
+
+impl From<serde_path_to_error::Error<serde_json::Error>> for JsonError {
+    fn from(error: serde_path_to_error::Error<serde_json::Error>) -> Self {
+        Self {
+            path: error.path().to_string(),
+            inner: error.into_inner(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for JsonError {
+    fn from(inner: serde_json::Error) -> Self {
+        Self {
+            path: ".".to_string(),
+            inner,
+        }
+    }
+}
+
+impl PartialEq for JsonError {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path && self.inner.to_string() == other.inner.to_string()
+    }
+}
+
+impl Display for JsonError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        write!(formatter, "{} at path {:?}", self.inner, self.path)
+    }
+}
+
+impl StdError for JsonError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.inner)
+    }
+}
+
+/// An io error together with the path of the file or directory it occurred at
+#[derive(Debug)]
+pub struct FileError {
+    path: PathBuf,
+    inner: io::Error,
+}
+
+impl FileError {
+    pub fn new(path: impl Into<PathBuf>, inner: io::Error) -> Self {
+        Self {
+            path: path.into(),
+            inner,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn inner(&self) -> &io::Error {
+        &self.inner
+    }
+}
+
+impl PartialEq for FileError {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path && self.inner.kind() == other.inner.kind()
+    }
+}
+
+impl Display for FileError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        write!(formatter, "{} at path {:?}", self.inner, self.path)
+    }
+}
+
+impl StdError for FileError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.inner)
+    }
+}
+
+/// Where the services of [`crate::get_services`] were read from
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ServicesSource {
+    /// The file at `VCAP_SERVICES_FILE_PATH`
+    File(PathBuf),
+    /// The `VCAP_SERVICES` variable
+    Env,
+    /// The servicebinding.io directory tree at `SERVICE_BINDING_ROOT`
+    BindingRoot(PathBuf),
+}
+
+impl ServicesSource {
+    /// The name of the variable the services came from
+    pub fn variable_name(&self) -> &'static str {
+        match self {
+            Self::File(_) => crate::constants::VCAP_SERVICES_FILE_PATH,
+            Self::Env => crate::constants::VCAP_SERVICES,
+            Self::BindingRoot(_) => crate::constants::SERVICE_BINDING_ROOT,
+        }
+    }
+}
+
+impl Display for ServicesSource {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::File(path) => write!(formatter, "file {path:?} from VCAP_SERVICES_FILE_PATH"),
+            Self::Env => write!(formatter, "environment variable \"VCAP_SERVICES\""),
+            Self::BindingRoot(path) => {
+                write!(formatter, "bindings {path:?} from SERVICE_BINDING_ROOT")
+            }
         }
     }
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+/// Binary units of `MEMORY_LIMIT`, a kilobyte is 1024 bytes as on Cloud Foundry
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub enum ByteUnit {
-    Gigabyte,
+    Byte,
+    Kilobyte,
     Megabyte,
+    Gigabyte,
+    Terabyte,
 }
 
-impl<'a> ByteUnit {
-    pub fn from_string(input: String) -> Result<Self, Error<'a>> {
-        let last_char = input.chars().next_back().unwrap();
+impl ByteUnit {
+    const ALL: [Self; 5] = [
+        Self::Byte,
+        Self::Kilobyte,
+        Self::Megabyte,
+        Self::Gigabyte,
+        Self::Terabyte,
+    ];
+
+    /// Reads the unit at the end of `input`, e.g. `M`, `mb` or `MiB` for megabytes
+    pub fn from_string(input: String) -> Result<Self, Error> {
+        Ok(Self::split(&input)?.1)
+    }
+
+    /// Splits `input` into the size in front of the unit and the unit
+    pub(crate) fn split(input: &str) -> Result<(&str, Self), Error> {
+        let upper = input.to_ascii_uppercase();
+        let (rest, plain_bytes) = if let Some(rest) = upper.strip_suffix("IB") {
+            (rest, false)
+        } else if let Some(rest) = upper.strip_suffix('B') {
+            (rest, true)
+        } else {
+            (upper.as_str(), false)
+        };
+
+        let unit = match rest.chars().next_back() {
+            Some('K') => Self::Kilobyte,
+            Some('M') => Self::Megabyte,
+            Some('G') => Self::Gigabyte,
+            Some('T') => Self::Terabyte,
+            _ if plain_bytes => return Ok((&input[..rest.len()], Self::Byte)),
+            _ => return Err(Error::UnknownMemoryUnit),
+        };
+        Ok((&input[..rest.len() - 1], unit))
+    }
+
+    /// The number of bytes of one unit
+    pub fn bytes(&self) -> u128 {
+        match self {
+            Self::Byte => 1,
+            Self::Kilobyte => 1 << 10,
+            Self::Megabyte => 1 << 20,
+            Self::Gigabyte => 1 << 30,
+            Self::Terabyte => 1 << 40,
+        }
+    }
+
+    /// The largest unit `bytes` is a whole multiple of
+    pub fn largest_fitting(bytes: u128) -> Self {
+        Self::ALL
+            .into_iter()
+            .rev()
+            .find(|unit| bytes % unit.bytes() == 0)
+            .filter(|_| bytes != 0)
+            .unwrap_or(Self::Byte)
+    }
 
-        match last_char {
-            'M' | 'm' => Ok(Self::Megabyte),
-            'G' | 'g' => Ok(Self::Gigabyte),
-            _ => Err(Error::UnknownMemoryUnit),
+    /// The symbol Cloud Foundry uses, e.g. `M`
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Byte => "B",
+            Self::Kilobyte => "K",
+            Self::Megabyte => "M",
+            Self::Gigabyte => "G",
+            Self::Terabyte => "T",
         }
     }
 }
 
+impl Display for ByteUnit {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        formatter.write_str(self.symbol())
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::error::Error as _;
+
+    fn json_error() -> crate::JsonError {
+        crate::enums::parse_json::<u16>("\"port\"").unwrap_err()
+    }
+
     #[test]
     fn byte_unit_gigabyte() {
         let unit = crate::ByteUnit::from_string("2G".to_string());
@@ -67,10 +435,44 @@ mod tests {
         assert_eq!(unit.unwrap(), crate::ByteUnit::Gigabyte);
     }
 
+    #[test]
+    fn byte_unit_variants() {
+        for (input, unit) in [
+            ("1k", crate::ByteUnit::Kilobyte),
+            ("1KB", crate::ByteUnit::Kilobyte),
+            ("1MiB", crate::ByteUnit::Megabyte),
+            ("1mib", crate::ByteUnit::Megabyte),
+            ("1Gb", crate::ByteUnit::Gigabyte),
+            ("1T", crate::ByteUnit::Terabyte),
+            ("1024B", crate::ByteUnit::Byte),
+        ] {
+            assert_eq!(
+                crate::ByteUnit::from_string(input.to_string()).unwrap(),
+                unit,
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn byte_unit_invalid() {
+        for input in ["", "512", "512X", "512iB", "B"] {
+            assert_eq!(
+                crate::ByteUnit::from_string(input.to_string()),
+                if input == "B" {
+                    Ok(crate::ByteUnit::Byte)
+                } else {
+                    Err(crate::Error::UnknownMemoryUnit)
+                },
+                "{input}"
+            );
+        }
+    }
+
     #[test]
     fn display_env_not_set() {
         assert_eq!(
-            format!("{}", crate::Error::EnvNotSet(crate::USER)),
+            format!("{}", crate::Error::EnvNotSet(crate::USER.to_string())),
             format!("environment variable {:?} is not set", crate::USER)
         );
     }
@@ -78,7 +480,10 @@ mod tests {
     #[test]
     fn display_json_mal_formed() {
         assert_eq!(
-            format!("{}", crate::Error::JsonMalformed(crate::USER.to_string())),
+            format!(
+                "{}",
+                crate::Error::JsonMalformed(crate::USER.to_string(), json_error())
+            ),
             format!("the json from {:?} could not be parsed", crate::USER)
         );
     }
@@ -86,15 +491,32 @@ mod tests {
     #[test]
     fn display_service_not_present() {
         assert_eq!(
-            format!("{}", crate::Error::ServiceNotPresent(crate::USER)),
+            format!(
+                "{}",
+                crate::Error::ServiceNotPresent(crate::USER.to_string())
+            ),
             format!("service {:?} is not present in VCAP_SERVICES", crate::USER)
         );
     }
 
+    #[test]
+    fn display_service_query_no_match() {
+        assert_eq!(
+            format!(
+                "{}",
+                crate::Error::ServiceQueryNoMatch("tag=mysql".to_string())
+            ),
+            "no service matches \"tag=mysql\""
+        );
+    }
+
     #[test]
     fn display_service_type_not_present() {
         assert_eq!(
-            format!("{}", crate::Error::ServiceTypeNotPresent(crate::USER)),
+            format!(
+                "{}",
+                crate::Error::ServiceTypeNotPresent(crate::USER.to_string())
+            ),
             format!(
                 "service type {:?} is not present in VCAP_SERVICES",
                 crate::USER
@@ -102,6 +524,36 @@ mod tests {
         );
     }
 
+    #[test]
+    fn display_service_malformed() {
+        assert_eq!(
+            format!(
+                "{}",
+                crate::Error::ServiceMalformed("cache".to_string(), "Has no uri".to_string())
+            ),
+            "the credentials of service \"cache\" do not match the required criteria. \"Has no uri\""
+        );
+    }
+
+    #[test]
+    fn serializes_kind_and_message() {
+        assert_eq!(
+            serde_json::to_value(crate::Error::EnvNotSet("PORT".to_string())).unwrap(),
+            serde_json::json!({
+                "kind": "env_not_set",
+                "message": "environment variable \"PORT\" is not set",
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(crate::Error::JsonMalformed(
+                "VCAP_SERVICES".to_string(),
+                json_error()
+            ))
+            .unwrap()["kind"],
+            "json_malformed"
+        );
+    }
+
     #[test]
     fn display_memory_unit_unknown() {
         assert_eq!(
@@ -110,6 +562,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn display_credhub_failed() {
+        assert_eq!(
+            format!(
+                "{}",
+                crate::Error::CredHubFailed("responded with status 403".to_string())
+            ),
+            "the credential references could not be resolved through CredHub: responded with status 403"
+        );
+    }
+
     #[test]
     fn display_env_mal_formed() {
         assert_eq!(
@@ -123,4 +586,76 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn error_kind() {
+        assert_eq!(
+            crate::Error::EnvNotSet(crate::USER.to_string()).kind(),
+            crate::ErrorKind::EnvNotSet
+        );
+        assert_eq!(
+            crate::Error::JsonMalformed(crate::USER.to_string(), json_error())
+                .kind()
+                .as_str(),
+            "json_malformed"
+        );
+    }
+
+    #[test]
+    fn error_is_boxable() {
+        let error: Box<dyn std::error::Error + Send + Sync + 'static> =
+            Box::new(crate::Error::UnknownMemoryUnit);
+
+        assert!(error.source().is_none());
+    }
+
+    #[test]
+    fn json_error_source_chain() {
+        #[derive(serde::Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Limits {
+            mem: u32,
+        }
+
+        let error = crate::enums::parse_json::<Vec<Limits>>("[\n{\"mem\": 1},\n{\"mem\": \"a\"}]")
+            .unwrap_err();
+
+        assert_eq!(error.path(), "[1].mem");
+        assert_eq!(error.line(), 3);
+        assert!(error.column() > 0);
+
+        let error = crate::Error::JsonMalformed(crate::VCAP_APPLICATION.to_string(), error);
+        let source = error.source().unwrap();
+        assert!(source.to_string().contains("[1].mem"));
+        assert!(source
+            .source()
+            .unwrap()
+            .downcast_ref::<serde_json::Error>()
+            .is_some());
+    }
+
+    #[test]
+    fn display_file_unreadable() {
+        let error = crate::Error::FileUnreadable(
+            crate::CF_INSTANCE_CERT.to_string(),
+            crate::FileError::new(
+                "/etc/cf-instance-credentials/instance.crt",
+                std::io::Error::from(std::io::ErrorKind::NotFound),
+            ),
+        );
+
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "the file from {:?} could not be read",
+                crate::CF_INSTANCE_CERT
+            )
+        );
+        assert_eq!(error.kind(), crate::ErrorKind::FileUnreadable);
+        assert!(error
+            .source()
+            .unwrap()
+            .to_string()
+            .contains("/etc/cf-instance-credentials/instance.crt"));
+    }
 }
@@ -0,0 +1,326 @@
+//! The instance identity certificate and platform CAs Diego provides to every container, requires the `identity` feature
+//!
+//! The certificate at `CF_INSTANCE_CERT` is signed by the platform and names the app, space and organization in its subject. The CAs in `CF_SYSTEM_CERT_PATH` can verify the certificates of other instances.
+use crate::constants::{CF_INSTANCE_CERT, CF_INSTANCE_KEY, CF_SYSTEM_CERT_PATH};
+use crate::enums::{Error, FileError};
+use guid_create::GUID;
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+const APP_PREFIX: &str = "app:";
+const SPACE_PREFIX: &str = "space:";
+const ORGANIZATION_PREFIX: &str = "organization:";
+
+/// The instance identity certificate Diego puts at the path in `CF_INSTANCE_CERT`
+///
+/// The certificate is short lived and rotated by the platform, check [`InstanceCertificate::not_after`] before relying on it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstanceCertificate {
+    /// The leaf certificate followed by its intermediates, as found in the file
+    pub chain: Vec<CertificateDer<'static>>,
+    pub not_before: SystemTime,
+    pub not_after: SystemTime,
+    /// The common name of the subject, the instance guid on Diego
+    pub common_name: Option<String>,
+    /// Parsed from the `OU=app:<guid>` entry of the subject
+    pub app_guid: Option<GUID>,
+    /// Parsed from the `OU=space:<guid>` entry of the subject
+    pub space_guid: Option<GUID>,
+    /// Parsed from the `OU=organization:<guid>` entry of the subject
+    pub organization_guid: Option<GUID>,
+}
+
+impl InstanceCertificate {
+    /// Parses a pem encoded certificate chain, leaf certificate first
+    pub fn from_pem(pem: &[u8]) -> Result<Self, Error> {
+        let chain = match CertificateDer::pem_slice_iter(pem).collect::<Result<Vec<_>, _>>() {
+            Ok(chain) if !chain.is_empty() => chain,
+            _ => {
+                return Err(Error::EnvMalformed(
+                    CF_INSTANCE_CERT.to_string(),
+                    "Doesn't contain a pem encoded certificate".to_string(),
+                ))
+            }
+        };
+
+        let leaf = match X509Certificate::from_der(&chain[0]) {
+            Ok((_, leaf)) => leaf,
+            Err(_) => {
+                return Err(Error::EnvMalformed(
+                    CF_INSTANCE_CERT.to_string(),
+                    "Isn't a valid x509 certificate".to_string(),
+                ))
+            }
+        };
+
+        let mut certificate = Self {
+            not_before: system_time(leaf.validity().not_before.timestamp()),
+            not_after: system_time(leaf.validity().not_after.timestamp()),
+            common_name: leaf
+                .subject()
+                .iter_common_name()
+                .find_map(|common_name| common_name.as_str().ok())
+                .map(str::to_string),
+            app_guid: None,
+            space_guid: None,
+            organization_guid: None,
+            chain: Vec::new(),
+        };
+
+        for unit in leaf.subject().iter_organizational_unit() {
+            let Ok(unit) = unit.as_str() else {
+                continue;
+            };
+            if let Some(guid) = unit.strip_prefix(APP_PREFIX) {
+                certificate.app_guid = Some(parse_guid(guid)?);
+            } else if let Some(guid) = unit.strip_prefix(SPACE_PREFIX) {
+                certificate.space_guid = Some(parse_guid(guid)?);
+            } else if let Some(guid) = unit.strip_prefix(ORGANIZATION_PREFIX) {
+                certificate.organization_guid = Some(parse_guid(guid)?);
+            }
+        }
+
+        certificate.chain = chain;
+        Ok(certificate)
+    }
+
+    /// Reads and parses the certificate chain at `path`
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_pem(&read(CF_INSTANCE_CERT, path.as_ref())?)
+    }
+
+    /// The leaf certificate of the chain
+    pub fn leaf(&self) -> &CertificateDer<'static> {
+        &self.chain[0]
+    }
+
+    /// Whether `time` lies within the validity window of the certificate
+    pub fn is_valid_at(&self, time: SystemTime) -> bool {
+        self.not_before <= time && time <= self.not_after
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.is_valid_at(SystemTime::now())
+    }
+
+    /// Time left until the certificate expires, `None` if it already did
+    pub fn expires_in(&self) -> Option<Duration> {
+        self.not_after.duration_since(SystemTime::now()).ok()
+    }
+}
+
+/// The instance identity certificate with its private key
+#[derive(Debug, PartialEq)]
+pub struct InstanceIdentity {
+    pub certificate: InstanceCertificate,
+    pub key: PrivateKeyDer<'static>,
+}
+
+impl InstanceIdentity {
+    /// Reads and parses the certificate chain and the private key from the given files
+    pub fn from_files(
+        certificate_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            certificate: InstanceCertificate::from_file(certificate_path)?,
+            key: parse_key_file(key_path)?,
+        })
+    }
+}
+
+impl Clone for InstanceIdentity {
+    fn clone(&self) -> Self {
+        Self {
+            certificate: self.certificate.clone(),
+            key: self.key.clone_key(),
+        }
+    }
+}
+
+/// Reads and parses the pem encoded private key at `path`
+pub fn parse_key_file(path: impl AsRef<Path>) -> Result<PrivateKeyDer<'static>, Error> {
+    parse_key(&read(CF_INSTANCE_KEY, path.as_ref())?)
+}
+
+/// Parses a pem encoded private key
+pub fn parse_key(pem: &[u8]) -> Result<PrivateKeyDer<'static>, Error> {
+    match PrivateKeyDer::from_pem_slice(pem) {
+        Ok(key) => Ok(key),
+        Err(_) => Err(Error::EnvMalformed(
+            CF_INSTANCE_KEY.to_string(),
+            "Doesn't contain a pem encoded private key".to_string(),
+        )),
+    }
+}
+
+/// Reads all pem encoded certificates of the files in the directory at `path`, e.g. the platform CAs in `CF_SYSTEM_CERT_PATH`
+///
+/// Files are read in the order of their names, files without any certificate are skipped.
+pub fn read_certificates_dir(
+    path: impl AsRef<Path>,
+) -> Result<Vec<CertificateDer<'static>>, Error> {
+    let path = path.as_ref();
+    let unreadable =
+        |err| Error::FileUnreadable(CF_SYSTEM_CERT_PATH.to_string(), FileError::new(path, err));
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path).map_err(unreadable)? {
+        let entry_path = entry.map_err(unreadable)?.path();
+        if entry_path.is_file() {
+            files.push(entry_path);
+        }
+    }
+    files.sort();
+
+    let mut certificates = Vec::new();
+    for file in files {
+        let content = read(CF_SYSTEM_CERT_PATH, &file)?;
+        certificates.extend(CertificateDer::pem_slice_iter(&content).map_while(Result::ok));
+    }
+    Ok(certificates)
+}
+
+pub(crate) fn read(variable_name: &str, path: &Path) -> Result<Vec<u8>, Error> {
+    match fs::read(path) {
+        Ok(content) => Ok(content),
+        Err(err) => Err(Error::FileUnreadable(
+            variable_name.to_string(),
+            FileError::new(path, err),
+        )),
+    }
+}
+
+fn parse_guid(guid: &str) -> Result<GUID, Error> {
+    match GUID::parse(guid) {
+        Ok(guid) => Ok(guid),
+        Err(_) => Err(Error::EnvMalformed(
+            CF_INSTANCE_CERT.to_string(),
+            format!("Subject contains the invalid guid {guid:?}"),
+        )),
+    }
+}
+
+fn system_time(timestamp: i64) -> SystemTime {
+    if timestamp >= 0 {
+        UNIX_EPOCH + Duration::from_secs(timestamp.unsigned_abs())
+    } else {
+        UNIX_EPOCH - Duration::from_secs(timestamp.unsigned_abs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CfEnv, InstanceCertificate};
+    use std::collections::HashMap;
+    use std::time::{Duration, SystemTime};
+
+    const CERT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/instance.crt");
+    const KEY_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/instance.key");
+    const SYSTEM_CERT_PATH: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/system-certificates"
+    );
+
+    fn guid(guid: &str) -> Option<guid_create::GUID> {
+        Some(guid_create::GUID::parse(guid).unwrap())
+    }
+
+    #[test]
+    fn parses_subject() {
+        let certificate = InstanceCertificate::from_file(CERT_PATH).unwrap();
+
+        assert_eq!(certificate.chain.len(), 2);
+        assert_eq!(
+            certificate.common_name.as_deref(),
+            Some("046463bc-1ba9-4046-bf5a-bd95672ee871")
+        );
+        assert_eq!(
+            certificate.app_guid,
+            guid("d8304a62-2df7-41d5-9211-0917c2253591")
+        );
+        assert_eq!(
+            certificate.space_guid,
+            guid("29349a46-ff0c-447e-bde0-db1be814f564")
+        );
+        assert_eq!(
+            certificate.organization_guid,
+            guid("26421037-ab23-4b51-99f8-9f5a6251fd26")
+        );
+    }
+
+    #[test]
+    fn validity_window() {
+        let certificate = InstanceCertificate::from_file(CERT_PATH).unwrap();
+
+        assert!(certificate.not_before < certificate.not_after);
+        assert!(certificate.is_valid_at(certificate.not_before));
+        assert!(!certificate.is_valid_at(certificate.not_after + Duration::from_secs(1)));
+        assert!(!certificate.is_valid_at(SystemTime::UNIX_EPOCH));
+    }
+
+    #[test]
+    fn identity_from_env() {
+        let env = CfEnv::new(HashMap::from([
+            ("CF_INSTANCE_CERT", CERT_PATH),
+            ("CF_INSTANCE_KEY", KEY_PATH),
+        ]));
+
+        let identity = env.get_instance_identity().unwrap();
+        assert_eq!(identity.certificate, env.get_instance_cert().unwrap());
+        assert_eq!(identity.key, env.get_instance_key().unwrap());
+    }
+
+    #[test]
+    fn identity_errors() {
+        let env = CfEnv::new(HashMap::from([
+            ("CF_INSTANCE_CERT", KEY_PATH),
+            ("CF_INSTANCE_KEY", "/does/not/exist.key"),
+        ]));
+
+        assert!(matches!(
+            env.get_instance_cert(),
+            Err(crate::Error::EnvMalformed(..))
+        ));
+        assert_eq!(
+            env.get_instance_key().unwrap_err().kind(),
+            crate::ErrorKind::FileUnreadable
+        );
+        assert_eq!(
+            CfEnv::new(HashMap::<String, String>::new())
+                .get_instance_identity()
+                .unwrap_err(),
+            crate::Error::EnvNotSet(crate::CF_INSTANCE_CERT.to_string())
+        );
+    }
+
+    #[test]
+    fn system_certificates() {
+        let env = CfEnv::new(HashMap::from([("CF_SYSTEM_CERT_PATH", SYSTEM_CERT_PATH)]));
+
+        let certificates = env.get_system_certificates().unwrap();
+        assert_eq!(certificates.len(), 1);
+        assert_eq!(
+            &certificates[0],
+            InstanceCertificate::from_file(CERT_PATH)
+                .unwrap()
+                .chain
+                .last()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn system_certificates_default_path() {
+        let env = CfEnv::new(HashMap::<String, String>::new());
+
+        assert_eq!(
+            env.get_system_cert_path(),
+            std::path::PathBuf::from(crate::DEFAULT_SYSTEM_CERT_PATH)
+        );
+    }
+}
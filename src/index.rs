@@ -0,0 +1,226 @@
+use crate::models::Service;
+use std::collections::HashMap;
+
+pub type ServiceMap = HashMap<String, Vec<Service>>;
+
+/// The services of `VCAP_SERVICES` with lookups by name, type, label, tag and instance name
+///
+/// Built once from a parsed [`ServiceMap`], lookups afterwards don't touch any json. Services are ordered by their type and then by their position in `VCAP_SERVICES`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ServiceIndex {
+    services: Vec<(String, Service)>,
+    by_name: HashMap<String, usize>,
+    by_instance_name: HashMap<String, usize>,
+    by_type: HashMap<String, Vec<usize>>,
+    by_label: HashMap<String, Vec<usize>>,
+    by_tag: HashMap<String, Vec<usize>>,
+}
+
+impl ServiceIndex {
+    pub fn new(services: ServiceMap) -> Self {
+        let mut index = Self::default();
+
+        let mut types = services.into_iter().collect::<Vec<_>>();
+        types.sort_by(|(left, _), (right, _)| left.cmp(right));
+
+        for (type_name, services) in types {
+            for service in services {
+                let position = index.services.len();
+
+                index
+                    .by_name
+                    .entry(service.name.clone())
+                    .or_insert(position);
+                index
+                    .by_instance_name
+                    .entry(service.instance_name.clone())
+                    .or_insert(position);
+                index
+                    .by_type
+                    .entry(type_name.clone())
+                    .or_default()
+                    .push(position);
+                index
+                    .by_label
+                    .entry(service.label.clone())
+                    .or_default()
+                    .push(position);
+                for tag in &service.tags {
+                    let positions = index.by_tag.entry(tag.clone()).or_default();
+                    if positions.last() != Some(&position) {
+                        positions.push(position);
+                    }
+                }
+
+                index.services.push((type_name.clone(), service));
+            }
+        }
+
+        index
+    }
+
+    fn resolve(&self, positions: &[usize]) -> Vec<&Service> {
+        positions
+            .iter()
+            .map(|position| &self.services[*position].1)
+            .collect()
+    }
+
+    /// Iterates over all services
+    pub fn iter(&self) -> impl Iterator<Item = &Service> {
+        self.services.iter().map(|(_, service)| service)
+    }
+
+    pub fn len(&self) -> usize {
+        self.services.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.services.is_empty()
+    }
+
+    /// Get's the service with the given `name`
+    pub fn by_name(&self, name: &str) -> Option<&Service> {
+        self.by_name
+            .get(name)
+            .map(|position| &self.services[*position].1)
+    }
+
+    /// Get's the service with the given `instance_name`
+    pub fn by_instance_name(&self, instance_name: &str) -> Option<&Service> {
+        self.by_instance_name
+            .get(instance_name)
+            .map(|position| &self.services[*position].1)
+    }
+
+    /// Get's the services listed under the given type, the keys of `VCAP_SERVICES`
+    pub fn by_type(&self, type_name: &str) -> Option<Vec<&Service>> {
+        self.by_type
+            .get(type_name)
+            .map(|positions| self.resolve(positions))
+    }
+
+    /// Get's the services with the given `label`
+    pub fn by_label(&self, label: &str) -> Vec<&Service> {
+        self.by_label
+            .get(label)
+            .map(|positions| self.resolve(positions))
+            .unwrap_or_default()
+    }
+
+    /// Get's the services tagged with `tag`
+    pub fn by_tag(&self, tag: &str) -> Vec<&Service> {
+        self.by_tag
+            .get(tag)
+            .map(|positions| self.resolve(positions))
+            .unwrap_or_default()
+    }
+
+    /// Turns the index back into the shape of `VCAP_SERVICES`
+    pub fn to_map(&self) -> ServiceMap {
+        let mut map = ServiceMap::new();
+        for (type_name, service) in &self.services {
+            map.entry(type_name.clone())
+                .or_default()
+                .push(service.clone());
+        }
+        map
+    }
+}
+
+impl From<ServiceMap> for ServiceIndex {
+    fn from(services: ServiceMap) -> Self {
+        Self::new(services)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    const SERVICE_DATA: &str = r#"
+    {
+        "postgres": [
+          {
+            "label": "postgres",
+            "plan": "small",
+            "name": "orders-db",
+            "tags": ["postgres", "relational"],
+            "instance_guid": "2b6e08f9-3174-46ff-999d-183dc4c4964d",
+            "instance_name": "orders-db-instance",
+            "binding_guid": "3290823d-ab9f-4d72-b414-2438144ea9dc",
+            "binding_name": null,
+            "credentials": { "uri": "postgres://u:p@orders:5432/orders" },
+            "syslog_drain_url": null,
+            "volume_mounts": []
+          },
+          {
+            "label": "postgres",
+            "plan": "large",
+            "name": "users-db",
+            "tags": ["postgres", "relational"],
+            "instance_guid": "720a4210-3ea0-44e0-b3e3-63ad833191a9",
+            "instance_name": "users-db-instance",
+            "binding_guid": "8d2b186f-22a6-48a8-bb38-df5320987812",
+            "binding_name": null,
+            "credentials": { "uri": "postgres://u:p@users:5432/users" },
+            "syslog_drain_url": null,
+            "volume_mounts": []
+          }
+        ],
+        "user-provided": [
+          {
+            "label": "user-provided",
+            "plan": "",
+            "name": "cache",
+            "tags": ["redis"],
+            "instance_guid": "046463bc-1ba9-4046-bf5a-bd95672ee871",
+            "instance_name": "cache",
+            "binding_guid": "26421037-ab23-4b51-99f8-9f5a6251fd26",
+            "binding_name": null,
+            "credentials": { "uri": "redis://cache:6379" },
+            "syslog_drain_url": null,
+            "volume_mounts": []
+          }
+        ]
+    }"#;
+
+    fn index() -> crate::ServiceIndex {
+        crate::ServiceIndex::new(serde_json::from_str(SERVICE_DATA).unwrap())
+    }
+
+    #[test]
+    fn lookups() {
+        let index = index();
+
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.by_name("users-db").unwrap().plan, "large");
+        assert_eq!(
+            index.by_instance_name("orders-db-instance").unwrap().name,
+            "orders-db"
+        );
+        assert_eq!(index.by_type("postgres").unwrap().len(), 2);
+        assert!(index.by_type("mysql").is_none());
+        assert_eq!(index.by_label("user-provided")[0].name, "cache");
+        assert_eq!(index.by_tag("redis")[0].name, "cache");
+        assert!(index.by_tag("mysql").is_empty());
+    }
+
+    #[test]
+    fn keeps_order_of_services() {
+        let index = index();
+        let names = index
+            .by_tag("relational")
+            .iter()
+            .map(|service| service.name.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, vec!["orders-db", "users-db"]);
+    }
+
+    #[test]
+    fn round_trips_to_map() {
+        assert_eq!(
+            index().to_map(),
+            serde_json::from_str::<crate::ServiceMap>(SERVICE_DATA).unwrap()
+        );
+    }
+}
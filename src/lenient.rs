@@ -0,0 +1,498 @@
+//! Lenient parsing of `VCAP_SERVICES` and `VCAP_APPLICATION` for older Cloud Foundry deployments and sloppy brokers
+//!
+//! Fields of services which were only added in later versions of Cloud Foundry get a default if they are missing and entries which can't be parsed at all are left out. Both is reported as [`ServiceWarning`] instead of failing.
+//!
+//! [`LenientApplication`] has every field optional and keeps the ones it doesn't know.
+use crate::constants::VCAP_APPLICATION;
+use crate::enums::{parse_json, parse_json_value, Error, JsonError};
+use crate::index::ServiceMap;
+use crate::models::{Application, Service, ServiceVolumeMount};
+use crate::route::{parse_routes, primary_external_url, Route};
+use guid_create::GUID;
+use http::Uri;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// Something lenient parsing of `VCAP_SERVICES` had to work around
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServiceWarning {
+    /// A version dependent field was missing and got a default, nil for guids, the service name for `instance_name` and empty for lists
+    FieldDefaulted {
+        label: String,
+        name: String,
+        field: &'static str,
+    },
+    /// The entry at `index` of `label` couldn't be parsed and was left out
+    EntrySkipped {
+        label: String,
+        index: usize,
+        reason: String,
+    },
+    /// The value of `label` isn't a list of services and was left out
+    LabelSkipped { label: String, reason: String },
+}
+
+impl Display for ServiceWarning {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::FieldDefaulted { label, name, field } => write!(
+                formatter,
+                "service {name:?} of {label:?} is missing {field:?}, using a default"
+            ),
+            Self::EntrySkipped {
+                label,
+                index,
+                reason,
+            } => write!(
+                formatter,
+                "skipped service {index} of {label:?} as it could not be parsed: {reason}"
+            ),
+            Self::LabelSkipped { label, reason } => write!(
+                formatter,
+                "skipped all services of {label:?} as they could not be parsed: {reason}"
+            ),
+        }
+    }
+}
+
+/// A [`Service`] with all version dependent fields being optional
+#[derive(Deserialize)]
+struct LenientService {
+    binding_guid: Option<GUID>,
+    binding_name: Option<String>,
+    instance_guid: Option<GUID>,
+    instance_name: Option<String>,
+    name: String,
+    label: String,
+    tags: Option<Vec<String>>,
+    plan: String,
+    credentials: Value,
+    syslog_drain_url: Option<String>,
+    volume_mounts: Option<Vec<ServiceVolumeMount>>,
+}
+
+/// Parses `VCAP_SERVICES` leniently, only failing if it isn't a json object at all
+pub fn parse_services_lenient(input: &str) -> Result<(ServiceMap, Vec<ServiceWarning>), JsonError> {
+    let mut services = ServiceMap::new();
+    let mut warnings = Vec::new();
+
+    for (label, entries) in parse_json::<Map<String, Value>>(input)? {
+        let entries = match parse_json_value::<Vec<Value>>(&entries) {
+            Ok(entries) => entries,
+            Err(err) => {
+                warnings.push(ServiceWarning::LabelSkipped {
+                    label,
+                    reason: err.to_string(),
+                });
+                continue;
+            }
+        };
+
+        let mut parsed = Vec::with_capacity(entries.len());
+        for (index, entry) in entries.iter().enumerate() {
+            match parse_json_value::<LenientService>(entry) {
+                Ok(service) => parsed.push(service.into_service(&label, &mut warnings)),
+                Err(err) => warnings.push(ServiceWarning::EntrySkipped {
+                    label: label.clone(),
+                    index,
+                    reason: err.to_string(),
+                }),
+            }
+        }
+        services.insert(label, parsed);
+    }
+
+    Ok((services, warnings))
+}
+
+impl LenientService {
+    fn into_service(self, label: &str, warnings: &mut Vec<ServiceWarning>) -> Service {
+        let mut defaulted = |field| {
+            warnings.push(ServiceWarning::FieldDefaulted {
+                label: label.to_string(),
+                name: self.name.clone(),
+                field,
+            })
+        };
+
+        let binding_guid = self.binding_guid.unwrap_or_else(|| {
+            defaulted("binding_guid");
+            GUID::default()
+        });
+        let instance_guid = self.instance_guid.unwrap_or_else(|| {
+            defaulted("instance_guid");
+            GUID::default()
+        });
+        let instance_name = self.instance_name.unwrap_or_else(|| {
+            defaulted("instance_name");
+            self.name.clone()
+        });
+        let tags = self.tags.unwrap_or_else(|| {
+            defaulted("tags");
+            Vec::new()
+        });
+        let volume_mounts = self.volume_mounts.unwrap_or_else(|| {
+            defaulted("volume_mounts");
+            Vec::new()
+        });
+
+        Service {
+            binding_guid,
+            binding_name: self.binding_name,
+            instance_guid,
+            instance_name,
+            name: self.name,
+            label: self.label,
+            tags,
+            plan: self.plan,
+            credentials: self.credentials,
+            syslog_drain_url: self.syslog_drain_url,
+            volume_mounts,
+        }
+    }
+}
+
+/// The generation of the `VCAP_APPLICATION` schema a foundation sends
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplicationSchema {
+    /// The DEA era, with `host`, `port`, `instance_id`, `instance_index` and `users`
+    Dea,
+    /// Diego with the v2 api, identifying the app by `application_id` and `application_version`
+    Diego,
+    /// Diego with the v3 api, adding the `organization_*` and `process_*` fields
+    V3,
+}
+
+/// `VCAP_APPLICATION` with every field optional, see [`crate::get_application_lenient`]
+///
+/// Works across foundations of different ages, use [`LenientApplication::schema`] to tell them apart and [`LenientApplication::to_application`] for the strict model.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct LenientApplication {
+    pub application_id: Option<GUID>,
+    pub application_name: Option<String>,
+    #[serde(default)]
+    pub application_uris: Vec<String>,
+    pub application_version: Option<GUID>,
+    pub cf_api: Option<String>,
+    pub host: Option<String>,
+    pub instance_id: Option<String>,
+    pub instance_index: Option<u16>,
+    pub limits: Option<LenientApplicationLimits>,
+    pub name: Option<String>,
+    pub organization_id: Option<GUID>,
+    pub organization_name: Option<String>,
+    pub port: Option<u16>,
+    pub process_id: Option<String>,
+    pub process_type: Option<String>,
+    pub space_id: Option<GUID>,
+    pub space_name: Option<String>,
+    #[serde(default, deserialize_with = "string_or_number")]
+    pub start: Option<String>,
+    #[serde(default, deserialize_with = "string_or_number")]
+    pub started_at: Option<String>,
+    #[serde(default, deserialize_with = "string_or_number")]
+    pub started_at_timestamp: Option<String>,
+    #[serde(default, deserialize_with = "string_or_number")]
+    pub state_timestamp: Option<String>,
+    #[serde(default)]
+    pub uris: Vec<String>,
+    pub users: Option<Value>,
+    pub version: Option<GUID>,
+    /// All fields not known to this crate
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct LenientApplicationLimits {
+    pub disk: Option<u128>,
+    pub fds: Option<u128>,
+    pub mem: Option<u128>,
+}
+
+impl LenientApplicationLimits {
+    /// `mem` in bytes, failing if it's too large to be a number of bytes
+    pub fn mem_bytes(&self) -> Result<Option<u128>, Error> {
+        megabytes_to_bytes("limits.mem", self.mem)
+    }
+
+    /// `disk` in bytes, failing if it's too large to be a number of bytes
+    pub fn disk_bytes(&self) -> Result<Option<u128>, Error> {
+        megabytes_to_bytes("limits.disk", self.disk)
+    }
+}
+
+fn megabytes_to_bytes(field: &str, megabytes: Option<u128>) -> Result<Option<u128>, Error> {
+    match megabytes.map(|megabytes| megabytes.checked_mul(1 << 20)) {
+        Some(None) => Err(Error::EnvMalformed(
+            VCAP_APPLICATION.to_string(),
+            format!("{field} is too large to be a number of bytes"),
+        )),
+        Some(bytes) => Ok(bytes),
+        None => Ok(None),
+    }
+}
+
+impl LenientApplication {
+    /// Parses `VCAP_APPLICATION`, only failing if it isn't a json object or a known field has the wrong type
+    pub fn from_json(input: &str) -> Result<Self, JsonError> {
+        parse_json(input)
+    }
+
+    /// Detects the schema generation from the fields present
+    pub fn schema(&self) -> ApplicationSchema {
+        if self.organization_id.is_some()
+            || self.organization_name.is_some()
+            || self.process_id.is_some()
+            || self.process_type.is_some()
+        {
+            ApplicationSchema::V3
+        } else if self.instance_id.is_some()
+            || self.instance_index.is_some()
+            || self.host.is_some()
+            || self.port.is_some()
+            || self.users.is_some()
+        {
+            ApplicationSchema::Dea
+        } else {
+            ApplicationSchema::Diego
+        }
+    }
+
+    /// The name of the application, from `application_name` or `name`
+    pub fn name(&self) -> Option<&str> {
+        self.application_name.as_deref().or(self.name.as_deref())
+    }
+
+    /// Parses the routes of `application_uris`, or `uris` on older foundations
+    pub fn routes(&self) -> Result<Vec<Route>, Error> {
+        parse_routes(&self.application_uris, &self.uris)
+    }
+
+    /// The url of the first route reachable from outside, neither internal nor TCP, e.g. for redirect uris and self links
+    pub fn primary_external_url(&self, scheme: &str) -> Result<Uri, Error> {
+        primary_external_url(&self.routes()?, scheme)
+    }
+
+    /// Converts into the strict model, failing with the first missing field
+    pub fn to_application(&self) -> Result<Application, Error> {
+        let converted = serde_json::to_value(self)
+            .map_err(JsonError::from)
+            .and_then(|value| parse_json_value::<Application>(&value));
+
+        match converted {
+            Ok(application) => Ok(application),
+            Err(err) => Err(Error::JsonMalformed(VCAP_APPLICATION.to_string(), err)),
+        }
+    }
+}
+
+/// Timestamps are strings on some foundations and epoch seconds on others
+fn string_or_number<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    match Option::<Value>::deserialize(deserializer)? {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(Value::Number(value)) => Ok(Some(value.to_string())),
+        Some(other) => Err(serde::de::Error::custom(format!(
+            "expected a string or a number, got {other}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CfEnv, ServiceWarning};
+    use std::collections::HashMap;
+
+    const SERVICE_DATA: &str = r#"
+    {
+        "mongodb": [
+          {
+            "label": "mongodb",
+            "plan": "small",
+            "name": "legacy-db",
+            "tags": ["mongodb"],
+            "credentials": { "uri": "mongodb://legacy" }
+          },
+          {
+            "label": "mongodb",
+            "name": "broken-db",
+            "credentials": {}
+          },
+          {
+            "label": "mongodb",
+            "plan": "small",
+            "name": "current-db",
+            "tags": [],
+            "instance_guid": "2b6e08f9-3174-46ff-999d-183dc4c4964d",
+            "instance_name": "current-db-instance",
+            "binding_guid": "3290823d-ab9f-4d72-b414-2438144ea9dc",
+            "binding_name": null,
+            "credentials": {},
+            "syslog_drain_url": null,
+            "volume_mounts": []
+          }
+        ],
+        "redis": {}
+    }"#;
+
+    #[test]
+    fn keeps_parsable_entries() {
+        let env = CfEnv::new(HashMap::from([("VCAP_SERVICES", SERVICE_DATA)])).lenient();
+
+        let (services, warnings) = env.get_services_with_warnings().unwrap();
+        assert_eq!(services.len(), 1);
+        assert_eq!(services["mongodb"].len(), 2);
+
+        let legacy = env
+            .get_service_by_name::<serde_json::Value>("legacy-db")
+            .unwrap();
+        assert_eq!(legacy.instance_guid, guid_create::GUID::default());
+        assert_eq!(legacy.instance_name, "legacy-db");
+        assert!(legacy.volume_mounts.is_empty());
+
+        assert_eq!(warnings.len(), 6);
+        assert!(warnings.contains(&ServiceWarning::FieldDefaulted {
+            label: "mongodb".to_string(),
+            name: "legacy-db".to_string(),
+            field: "binding_guid",
+        }));
+        assert!(matches!(
+            &warnings[4],
+            ServiceWarning::EntrySkipped { label, index: 1, reason } if label == "mongodb" && reason.contains("plan")
+        ));
+        assert!(matches!(
+            &warnings[5],
+            ServiceWarning::LabelSkipped { label, .. } if label == "redis"
+        ));
+    }
+
+    #[test]
+    fn strict_by_default() {
+        let env = CfEnv::new(HashMap::from([("VCAP_SERVICES", SERVICE_DATA)]));
+
+        assert_eq!(
+            env.get_services().unwrap_err().kind(),
+            crate::ErrorKind::JsonMalformed
+        );
+        assert_eq!(
+            env.lenient().get_services_with_warnings().unwrap().0.len(),
+            1
+        );
+    }
+
+    #[test]
+    fn loaded_keeps_warnings() {
+        let env =
+            CfEnv::load_lenient_from(HashMap::from([("VCAP_SERVICES", SERVICE_DATA)])).unwrap();
+
+        assert_eq!(env.get_services_with_warnings().unwrap().1.len(), 6);
+        assert!(env
+            .get_service_by_name::<serde_json::Value>("broken-db")
+            .is_err());
+        assert_eq!(env.service_index().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn not_an_object() {
+        let env = CfEnv::new(HashMap::from([("VCAP_SERVICES", "[]")])).lenient();
+
+        assert_eq!(
+            env.get_services_with_warnings().unwrap_err().kind(),
+            crate::ErrorKind::JsonMalformed
+        );
+    }
+
+    const DEA_APP_DATA: &str = r#"
+    {
+        "instance_id": "451f045fd16427bb99c895a2649b7b2a",
+        "instance_index": 0,
+        "host": "0.0.0.0",
+        "port": 61857,
+        "started_at": "2013-08-12 00:05:29 +0000",
+        "started_at_timestamp": 1376265929,
+        "start": "2013-08-12 00:05:29 +0000",
+        "state_timestamp": 1376265929,
+        "limits": { "mem": 512, "disk": 1024, "fds": 16384 },
+        "application_version": "9fe9fe07-c7b7-415b-afa3-75fef5258d47",
+        "application_name": "styx-james",
+        "application_uris": ["styx-james.a1-app.cf-app.com"],
+        "version": "9fe9fe07-c7b7-415b-afa3-75fef5258d47",
+        "name": "styx-james",
+        "uris": ["styx-james.a1-app.cf-app.com"],
+        "users": null
+    }"#;
+
+    const V3_APP_DATA: &str = r#"
+    {
+        "cf_api": "https://api.example.io",
+        "limits": { "fds": 12384, "mem": 512, "disk": 1024 },
+        "application_name": "my-backend",
+        "application_uris": ["backend.example.io"],
+        "name": "my-backend",
+        "space_name": "production",
+        "space_id": "29349a46-ff0c-447e-bde0-db1be814f564",
+        "organization_id": "26421037-ab23-4b51-99f8-9f5a6251fd26",
+        "organization_name": "Project_One",
+        "uris": ["backend.example.io"],
+        "process_id": "d8304a62-2df7-41d5-9211-0917c2253591",
+        "process_type": "web",
+        "application_id": "d8304a62-2df7-41d5-9211-0917c2253591",
+        "version": "9fe9fe07-c7b7-415b-afa3-75fef5258d47",
+        "application_version": "9fe9fe07-c7b7-415b-afa3-75fef5258d47",
+        "sidecars": ["envoy"]
+    }"#;
+
+    #[test]
+    fn dea_application() {
+        let env = CfEnv::new(HashMap::from([("VCAP_APPLICATION", DEA_APP_DATA)]));
+
+        let application = env.get_application_lenient().unwrap();
+        assert_eq!(application.schema(), crate::ApplicationSchema::Dea);
+        assert_eq!(application.instance_index, Some(0));
+        assert_eq!(application.port, Some(61857));
+        assert_eq!(application.state_timestamp.as_deref(), Some("1376265929"));
+        assert_eq!(application.name(), Some("styx-james"));
+        assert!(application.extra.is_empty());
+
+        assert_eq!(
+            env.get_application_info().unwrap_err().kind(),
+            crate::ErrorKind::JsonMalformed
+        );
+        assert!(application.to_application().is_err());
+    }
+
+    #[test]
+    fn v3_application() {
+        let env = CfEnv::new(HashMap::from([("VCAP_APPLICATION", V3_APP_DATA)]));
+
+        let application = env.get_application_lenient().unwrap();
+        assert_eq!(application.schema(), crate::ApplicationSchema::V3);
+        assert_eq!(application.extra["sidecars"], serde_json::json!(["envoy"]));
+        assert_eq!(
+            application.to_application().unwrap(),
+            env.get_application_info().unwrap()
+        );
+    }
+
+    #[test]
+    fn loaded_application() {
+        let strict = CfEnv::load_from(HashMap::from([("VCAP_APPLICATION", DEA_APP_DATA)]));
+        assert_eq!(strict.unwrap_err().kind(), crate::ErrorKind::JsonMalformed);
+
+        let env =
+            CfEnv::load_lenient_from(HashMap::from([("VCAP_APPLICATION", DEA_APP_DATA)])).unwrap();
+        assert_eq!(
+            env.get_application_lenient().unwrap().schema(),
+            crate::ApplicationSchema::Dea
+        );
+        assert_eq!(
+            env.get_application_info().unwrap_err().kind(),
+            crate::ErrorKind::JsonMalformed
+        );
+    }
+}
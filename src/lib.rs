@@ -6,234 +6,405 @@
 //! - Cloud Foundry app instance set variables, most of the times starting with `CF_`
 //!
 //! It's meant to get you away from boilerplating and get you started with typed variables while keeping simplicity
+//!
+//! The free functions read from the process environment. Use [`CfEnv`] with another [`EnvSource`] to read from somewhere else.
 #![deny(clippy::all, clippy::cargo)]
+// serde_derive is on syn 3 while guid-create still pulls in syn 2 through rand and zerocopy-derive
+#![allow(clippy::multiple_crate_versions)]
 #![forbid(unsafe_code)]
 
+pub mod bindings;
+pub mod budget;
+pub mod cf_env;
+pub mod cgroup;
+#[cfg(feature = "sqlx")]
+pub mod connect_options;
+pub mod connection;
 pub mod constants;
+#[cfg(feature = "credentials")]
+pub mod credentials;
+#[cfg(feature = "credhub")]
+pub mod credhub;
+pub mod database;
+pub mod disk;
 pub mod enums;
+#[cfg(feature = "identity")]
+pub mod identity;
+pub mod index;
+pub mod lenient;
+pub mod locale;
 pub mod models;
+pub mod query;
+#[cfg(feature = "reload")]
+pub mod reload;
+pub mod route;
+pub mod source;
+pub mod timestamp;
+#[cfg(feature = "rustls")]
+pub mod tls;
 
+#[doc(hidden)]
+pub use bindings::*;
+#[doc(hidden)]
+pub use budget::*;
+// `self::` as the module shares its name with the crate, which older rustdoc can't tell apart in doctests
+#[doc(hidden)]
+pub use self::cf_env::*;
+#[doc(hidden)]
+pub use cgroup::*;
+#[cfg(feature = "sqlx")]
+#[doc(hidden)]
+pub use connect_options::*;
+#[doc(hidden)]
+pub use connection::*;
 #[doc(hidden)]
 pub use constants::*;
+#[cfg(feature = "credentials")]
+#[doc(hidden)]
+pub use credentials::*;
+#[cfg(feature = "credhub")]
+#[doc(hidden)]
+pub use credhub::*;
+#[doc(hidden)]
+pub use database::*;
+#[doc(hidden)]
+pub use disk::*;
 #[doc(hidden)]
 pub use enums::*;
+#[cfg(feature = "identity")]
+#[doc(hidden)]
+pub use identity::*;
+#[doc(hidden)]
+pub use index::*;
+#[doc(hidden)]
+pub use lenient::*;
+#[doc(hidden)]
+pub use locale::*;
 #[doc(hidden)]
 pub use models::*;
+#[doc(hidden)]
+pub use query::*;
+#[cfg(feature = "reload")]
+#[doc(hidden)]
+pub use reload::*;
+#[doc(hidden)]
+pub use route::*;
+#[doc(hidden)]
+pub use source::*;
+#[doc(hidden)]
+pub use timestamp::*;
+
+#[cfg(feature = "identity")]
+pub use rustls_pki_types as pki_types;
 
 use guid_create::GUID;
 use http::Uri;
 use locale_types::Locale;
+#[cfg(feature = "identity")]
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
 use serde::de::DeserializeOwned;
-use std::collections::HashMap;
-use std::env;
 use std::net::{IpAddr, SocketAddr};
-use std::panic;
 use std::path::PathBuf;
-use std::str::FromStr;
+
+fn process_env() -> CfEnv<ProcessEnv> {
+    CfEnv::new(ProcessEnv)
+}
 
 /// Checks if `VCAP_APPLICATION` is defined, if so uses as the indicator that the app is running in a Cloud Foundry Environment.
 ///
 /// Use this with caution. To use the flexibility of cargo and optimization of rust and llvm you should only use this if there is no other way. One other possible way would be to use features flags for your binaries and use them to identify for which environment you build.
 pub fn is_cf_env() -> bool {
-    env::var(VCAP_APPLICATION).is_ok()
+    process_env().is_cf_env()
 }
 
 /// Get's the value from `CF_INSTANCE_ADDR` as a typed SocketAddr
-pub fn get_instance_address() -> Result<SocketAddr, Error<'static>> {
-    match env::var(CF_INSTANCE_ADDR) {
-        Ok(addr_string) => match addr_string.parse::<SocketAddr>() {
-            Ok(socket) => Ok(socket),
-            Err(_) => Err(Error::EnvMalformed(
-                CF_INSTANCE_ADDR.to_string(),
-                "Doesn't match the format of addr:ip".to_string(),
-            )),
-        },
-        Err(_) => Err(Error::EnvNotSet(CF_INSTANCE_ADDR)),
-    }
+pub fn get_instance_address() -> Result<SocketAddr, Error> {
+    process_env().get_instance_address()
+}
+
+/// Get's the instance identity certificate from the file at `CF_INSTANCE_CERT`
+///
+/// Diego issues it for every container, the subject contains the guids of the app, space and organization. Requires the `identity` feature.
+///
+/// ```no_run
+/// let certificate = cf_env::get_instance_cert().unwrap();
+///
+/// let app_guid = certificate.app_guid.unwrap();
+/// let expires_in = certificate.expires_in();
+/// ```
+#[cfg(feature = "identity")]
+pub fn get_instance_cert() -> Result<InstanceCertificate, Error> {
+    process_env().get_instance_cert()
+}
+
+/// Get's the private key of the instance identity certificate from the file at `CF_INSTANCE_KEY`, requires the `identity` feature
+#[cfg(feature = "identity")]
+pub fn get_instance_key() -> Result<PrivateKeyDer<'static>, Error> {
+    process_env().get_instance_key()
+}
+
+/// Get's the instance identity certificate together with its private key, requires the `identity` feature
+#[cfg(feature = "identity")]
+pub fn get_instance_identity() -> Result<InstanceIdentity, Error> {
+    process_env().get_instance_identity()
+}
+
+/// Get's the value from `CF_SYSTEM_CERT_PATH` as a typed PathBuf, falling back to [`DEFAULT_SYSTEM_CERT_PATH`]
+pub fn get_system_cert_path() -> PathBuf {
+    process_env().get_system_cert_path()
+}
+
+/// Get's the platform CA certificates from the directory at `CF_SYSTEM_CERT_PATH`, requires the `identity` feature
+#[cfg(feature = "identity")]
+pub fn get_system_certificates() -> Result<Vec<CertificateDer<'static>>, Error> {
+    process_env().get_system_certificates()
+}
+
+/// Builds a rustls server config for mutual tls on container to container routes
+///
+/// Presents the instance identity certificate and requires clients to present a certificate signed by one of the platform CAs in `CF_SYSTEM_CERT_PATH`. Requires the `rustls` feature.
+///
+/// ```no_run
+/// use std::sync::Arc;
+///
+/// let config = Arc::new(cf_env::get_tls_server_config().unwrap());
+/// ```
+#[cfg(feature = "rustls")]
+pub fn get_tls_server_config() -> Result<rustls::ServerConfig, Error> {
+    process_env().get_tls_server_config()
+}
+
+/// Builds a rustls client config to call other apps
+///
+/// Trusts the platform CAs in `CF_SYSTEM_CERT_PATH` and presents the instance identity certificate as client certificate. Requires the `rustls` feature.
+#[cfg(feature = "rustls")]
+pub fn get_tls_client_config() -> Result<rustls::ClientConfig, Error> {
+    process_env().get_tls_client_config()
+}
+
+/// Starts watching the instance identity files, swapping in the certificates Diego rotates
+///
+/// Hand the resolver to your rustls configs so long running servers keep working after a rotation. Requires the `reload` feature.
+///
+/// ```no_run
+/// use std::sync::Arc;
+///
+/// let watcher = cf_env::watch_instance_identity(cf_env::DEFAULT_RELOAD_INTERVAL).unwrap();
+/// let roots = cf_env::tls::root_store(cf_env::get_system_certificates().unwrap()).unwrap();
+/// let config = Arc::new(watcher.resolver().server_config(roots).unwrap());
+///
+/// // keep `watcher` alive as long as the server runs
+/// ```
+#[cfg(feature = "reload")]
+pub fn watch_instance_identity(
+    interval: std::time::Duration,
+) -> Result<reload::CertificateWatcher, Error> {
+    process_env().watch_instance_identity(interval)
 }
 
 /// Get's the value from `CF_INSTANCE_GUID` as a typed GUID
-pub fn get_instance_guid() -> Result<GUID, Error<'static>> {
-    match env::var(CF_INSTANCE_GUID) {
-        Ok(guid_string) => match GUID::parse(&guid_string) {
-            Ok(result) => Ok(result),
-            Err(_) => Err(Error::EnvMalformed(
-                CF_INSTANCE_GUID.to_string(),
-                "Isn't a valid guid".to_string(),
-            )),
-        },
-        Err(_) => Err(Error::EnvNotSet(CF_INSTANCE_GUID)),
-    }
+pub fn get_instance_guid() -> Result<GUID, Error> {
+    process_env().get_instance_guid()
 }
 
 /// Get's the value from `CF_INSTANCE_INDEX` as a typed u128
-pub fn get_instance_index() -> Result<u128, Error<'static>> {
-    match env::var(CF_INSTANCE_INDEX) {
-        Ok(index_string) => match index_string.parse::<u128>() {
-            Ok(result) => Ok(result),
-            Err(_) => Err(Error::EnvMalformed(
-                CF_INSTANCE_INDEX.to_string(),
-                "Ins't a valid positive (u128) number".to_string(),
-            )),
-        },
-        Err(_) => Err(Error::EnvNotSet(CF_INSTANCE_INDEX)),
-    }
+pub fn get_instance_index() -> Result<u128, Error> {
+    process_env().get_instance_index()
 }
 
 /// Get's the value from `CF_INSTANCE_IP` as a typed IpAddr
-pub fn get_instance_ip() -> Result<IpAddr, Error<'static>> {
-    match env::var(CF_INSTANCE_IP) {
-        Ok(ip_string) => match ip_string.parse::<IpAddr>() {
-            Ok(result) => Ok(result),
-            Err(_) => Err(Error::EnvMalformed(
-                CF_INSTANCE_IP.to_string(),
-                "Ins't a valid ip address".to_string(),
-            )),
-        },
-        Err(_) => Err(Error::EnvNotSet(CF_INSTANCE_IP)),
-    }
+pub fn get_instance_ip() -> Result<IpAddr, Error> {
+    process_env().get_instance_ip()
 }
 
 /// Get's the value from `CF_INSTANCE_INTERNAL_IP` as a typed IpAddr
-pub fn get_instance_internal_ip() -> Result<IpAddr, Error<'static>> {
-    match env::var(CF_INSTANCE_INTERNAL_IP) {
-        Ok(ip_string) => match ip_string.parse::<IpAddr>() {
-            Ok(result) => Ok(result),
-            Err(_) => Err(Error::EnvMalformed(
-                CF_INSTANCE_INTERNAL_IP.to_string(),
-                "Ins't a valid ip address".to_string(),
-            )),
-        },
-        Err(_) => Err(Error::EnvNotSet(CF_INSTANCE_INTERNAL_IP)),
-    }
+pub fn get_instance_internal_ip() -> Result<IpAddr, Error> {
+    process_env().get_instance_internal_ip()
 }
 
 /// Get's the value from `CF_INSTANCE_PORT` as a typed u16
-pub fn get_instance_port() -> Result<u16, Error<'static>> {
-    match env::var(CF_INSTANCE_PORT) {
-        Ok(index_string) => match index_string.parse::<u16>() {
-            Ok(result) => Ok(result),
-            Err(_) => Err(Error::EnvMalformed(
-                CF_INSTANCE_PORT.to_string(),
-                "Ins't a valid positive (u16) number".to_string(),
-            )),
-        },
-        Err(_) => Err(Error::EnvNotSet(CF_INSTANCE_PORT)),
-    }
+pub fn get_instance_port() -> Result<u16, Error> {
+    process_env().get_instance_port()
+}
+
+/// Get's the value from `CF_INSTANCE_PORTS` as typed port mappings
+///
+/// Use it to find the externally reachable port of the port your app listens on
+///
+/// ```no_run
+/// let port = cf_env::get_port().unwrap();
+/// let external = cf_env::get_instance_ports().unwrap().external_for(port);
+/// ```
+pub fn get_instance_ports() -> Result<InstancePorts, Error> {
+    process_env().get_instance_ports()
 }
 
 /// Get's the value from `DATABASE_URL` as a typed Uri
-pub fn get_database_url() -> Result<Uri, Error<'static>> {
-    match env::var(DATABASE_URL) {
-        Ok(index_string) => match index_string.parse::<Uri>() {
-            Ok(result) => Ok(result),
-            Err(_) => Err(Error::EnvMalformed(
-                DATABASE_URL.to_string(),
-                "Ins't a valid uri".to_string(),
-            )),
-        },
-        Err(_) => Err(Error::EnvNotSet(DATABASE_URL)),
-    }
+pub fn get_database_url() -> Result<Uri, Error> {
+    process_env().get_database_url()
+}
+
+/// Get's the database connection info from `DATABASE_URL`, or else from the first bound relational service by service type name
+///
+/// The order of the service types in `VCAP_SERVICES` isn't kept, so services are taken by their type name, e.g. `a9s-postgresql13` before `p.mysql`, and within a type in the order they are listed. With more than one relational service bound, pick one with [`get_database_config_for`].
+///
+/// ```no_run
+/// let database = cf_env::get_database_config().unwrap();
+///
+/// println!(
+///     "Connecting to {} on {}:{}",
+///     database.database.as_deref().unwrap_or_default(),
+///     database.host,
+///     database.port_or_default().unwrap_or_default()
+/// );
+/// ```
+pub fn get_database_config() -> Result<DatabaseConfig, Error> {
+    process_env().get_database_config()
+}
+
+/// Get's the database connection info from the credentials of the service named `name`
+pub fn get_database_config_for(name: &str) -> Result<DatabaseConfig, Error> {
+    process_env().get_database_config_for(name)
+}
+
+/// Builds sqlx Postgres options from `DATABASE_URL`, or else from the first bound Postgres service by service type name
+///
+/// Services are taken in the same order as by [`get_database_config`]. The CA certificate and `sslmode` the broker provides are applied. Requires the `sqlx` feature.
+///
+/// ```no_run
+/// let options = cf_env::get_pg_connect_options().unwrap();
+/// // sqlx::PgPool::connect_with(options).await
+/// ```
+#[cfg(feature = "sqlx")]
+pub fn get_pg_connect_options() -> Result<sqlx::postgres::PgConnectOptions, Error> {
+    process_env().get_pg_connect_options()
+}
+
+/// Builds sqlx MySQL options from `DATABASE_URL`, or else from the first bound MySQL service by service type name
+///
+/// Services are taken in the same order as by [`get_database_config`]. The CA certificate and `ssl-mode` the broker provides are applied. Requires the `sqlx` feature.
+#[cfg(feature = "sqlx")]
+pub fn get_mysql_connect_options() -> Result<sqlx::mysql::MySqlConnectOptions, Error> {
+    process_env().get_mysql_connect_options()
+}
+
+/// Builds sqlx pool options sized by `MEMORY_LIMIT` and the plan of `service`, see [`pool_max_connections`]. Requires the `sqlx` feature.
+#[cfg(feature = "sqlx")]
+pub fn get_pool_options<DB: sqlx::Database>(
+    service: Option<&Service>,
+) -> Result<sqlx::pool::PoolOptions<DB>, Error> {
+    process_env().get_pool_options(service)
 }
 
 /// Get's the value from `HOME` as a typed PathBuf
-pub fn get_home() -> Result<PathBuf, Error<'static>> {
-    match env::var(HOME) {
-        Ok(home_string) => Ok(PathBuf::from(home_string)),
-        Err(_) => Err(Error::EnvNotSet(HOME)),
-    }
+pub fn get_home() -> Result<PathBuf, Error> {
+    process_env().get_home()
 }
 
 /// Get's the value from `LANG` as a typed Locale
-pub fn get_lang() -> Result<Locale, Error<'static>> {
-    match env::var(LANG) {
-        Ok(lang_string) => {
-            let parse_result = panic::catch_unwind(|| Locale::from_str(&lang_string));
-
-            if parse_result.is_err() {
-                return Err(Error::EnvMalformed(
-                    LANG.to_string(),
-                    "Ins't a valid locale".to_string(),
-                ));
-            }
+pub fn get_lang() -> Result<Locale, Error> {
+    process_env().get_lang()
+}
 
-            match parse_result.unwrap() {
-                Ok(result) => Ok(result),
-                Err(_) => Err(Error::EnvMalformed(
-                    LANG.to_string(),
-                    "Ins't a valid locale".to_string(),
-                )),
-            }
-        }
-        Err(_) => Err(Error::EnvNotSet(LANG)),
-    }
+/// Get's the locale from the first set of `LC_ALL`, `LC_MESSAGES` and `LANG`, like the C library does
+///
+/// ```no_run
+/// let locale = cf_env::get_locale().unwrap();
+/// if !locale.is_posix() {
+///     println!("Translating to {}", locale.language);
+/// }
+/// ```
+pub fn get_locale() -> Result<PosixLocale, Error> {
+    process_env().get_locale()
 }
 
 /// Get's the value from `MEMORY_LIMIT` as a typed MemoryLimit
-pub fn get_memory_limit() -> Result<MemoryLimit, Error<'static>> {
-    match env::var(MEMORY_LIMIT) {
-        Ok(memory_string) => {
-            match MemoryLimit::from_string(memory_string, MEMORY_LIMIT.to_string()) {
-                Ok(result) => Ok(result),
-                Err(_) => Err(Error::EnvMalformed(
-                    MEMORY_LIMIT.to_string(),
-                    "Ins't a valid memory size formatted after '<size><unit>'".to_string(),
-                )),
-            }
-        }
-        Err(_) => Err(Error::EnvNotSet(MEMORY_LIMIT)),
-    }
+pub fn get_memory_limit() -> Result<MemoryLimit, Error> {
+    process_env().get_memory_limit()
+}
+
+/// Compares `MEMORY_LIMIT` and the limits of `VCAP_APPLICATION` with the ones the container enforces
+///
+/// ```no_run
+/// use cf_env::ContainerLimits;
+///
+/// let limits = ContainerLimits::read();
+/// for mismatch in cf_env::check_container_limits(&limits).unwrap() {
+///     eprintln!("{mismatch}");
+/// }
+///
+/// // e.g. for the worker threads of a tokio runtime
+/// let worker_threads = limits.worker_threads();
+/// ```
+pub fn check_container_limits(limits: &ContainerLimits) -> Result<Vec<LimitMismatch>, Error> {
+    process_env().check_container_limits(limits)
+}
+
+/// Measures the disk usage of `HOME`, `TMPDIR` and `PWD` against `limits.disk` of `VCAP_APPLICATION`
+///
+/// Walks all files, so don't call it on every request.
+///
+/// ```no_run
+/// let usage = cf_env::get_disk_usage().unwrap();
+///
+/// if usage.exceeds(80.0) {
+///     // evict some cache entries
+/// }
+/// ```
+pub fn get_disk_usage() -> Result<DiskUsage, Error> {
+    process_env().get_disk_usage()
+}
+
+/// Get's a [`MemoryBudget`] to split the value from `MEMORY_LIMIT` into named allocations
+pub fn get_memory_budget() -> Result<MemoryBudget, Error> {
+    process_env().get_memory_budget()
 }
 
 /// Get's the value from `PORT` as a typed 16
-pub fn get_port() -> Result<u16, Error<'static>> {
-    match env::var(PORT) {
-        Ok(port_string) => match port_string.parse::<u16>() {
-            Ok(result) => Ok(result),
-            Err(_) => Err(Error::EnvMalformed(
-                PORT.to_string(),
-                "Ins't a valid positive (u16) number".to_string(),
-            )),
-        },
-        Err(_) => Err(Error::EnvNotSet(PORT)),
-    }
+pub fn get_port() -> Result<u16, Error> {
+    process_env().get_port()
 }
 
 /// Get's the value from `PWD` as a typed PathBuf
-pub fn get_pwd() -> Result<PathBuf, Error<'static>> {
-    match env::var(PWD) {
-        Ok(pwd_string) => Ok(PathBuf::from(pwd_string)),
-        Err(_) => Err(Error::EnvNotSet(PWD)),
-    }
+pub fn get_pwd() -> Result<PathBuf, Error> {
+    process_env().get_pwd()
 }
 
 /// Get's the value from `TMPDIR` as a typed PathBuf
-pub fn get_tmp_dir() -> Result<PathBuf, Error<'static>> {
-    match env::var(TMPDIR) {
-        Ok(tmp_dir) => Ok(PathBuf::from(tmp_dir)),
-        Err(_) => Err(Error::EnvNotSet(TMPDIR)),
-    }
+pub fn get_tmp_dir() -> Result<PathBuf, Error> {
+    process_env().get_tmp_dir()
 }
 
 /// Get's the value from `USER`
-pub fn get_user() -> Result<String, Error<'static>> {
-    match env::var(USER) {
-        Ok(user_string) => Ok(user_string),
-        Err(_) => Err(Error::EnvNotSet(USER)),
-    }
+pub fn get_user() -> Result<String, Error> {
+    process_env().get_user()
 }
 
-type ServiceMap = HashMap<String, Vec<Service>>;
-
 /// Get's the value from `VCAP_SERVICES` as a typed HashMap of Strings and a list of Services
-pub fn get_services() -> Result<ServiceMap, Error<'static>> {
-    match env::var(VCAP_SERVICES) {
-        Ok(services) => match serde_json::from_str::<ServiceMap>(&services) {
-            Ok(value) => Ok(value),
-            Err(_err) => Err(Error::JsonMalformed(VCAP_SERVICES.to_string())),
-        },
-        Err(_) => Err(Error::EnvNotSet(VCAP_SERVICES)),
-    }
+///
+/// If `VCAP_SERVICES_FILE_PATH` is set, the services are read from that file instead. Without either of them the servicebinding.io bindings at `SERVICE_BINDING_ROOT` are read, as found on kubernetes based platforms. See [`get_services_source`], this applies to all service lookups.
+pub fn get_services() -> Result<ServiceMap, Error> {
+    process_env().get_services()
+}
+
+/// Get's the services leniently parsed, together with the warnings about what had to be worked around
+///
+/// Entries missing fields older Cloud Foundry versions don't set get defaults, entries which can't be parsed at all are left out.
+///
+/// ```no_run
+/// let (services, warnings) = cf_env::get_services_lenient().unwrap();
+///
+/// for warning in warnings {
+///     eprintln!("{warning}");
+/// }
+/// ```
+pub fn get_services_lenient() -> Result<(ServiceMap, Vec<ServiceWarning>), Error> {
+    process_env().lenient().get_services_with_warnings()
+}
+
+/// Get's where the services are read from, the file at `VCAP_SERVICES_FILE_PATH`, `VCAP_SERVICES` or the bindings at `SERVICE_BINDING_ROOT`
+pub fn get_services_source() -> Result<ServicesSource, Error> {
+    process_env().get_services_source()
 }
 
 /// Get's you a single service from`VCAP_SERVICES` by it's name
@@ -270,28 +441,7 @@ pub fn get_service_by_name<T>(name: &str) -> Result<Service<T>, Error>
 where
     T: DeserializeOwned,
 {
-    match get_services() {
-        Ok(services) => {
-            for key in services.keys() {
-                for service in services.get(key).unwrap().iter() {
-                    if service.name == name {
-                        let service_json = serde_json::to_string(service).unwrap();
-                        match serde_json::from_str::<Service<T>>(&service_json) {
-                            Ok(service) => return Ok(service),
-                            Err(_) => {
-                                return Err(Error::JsonMalformed(format!(
-                                    "{}.credentials",
-                                    service.name.to_owned()
-                                )))
-                            }
-                        }
-                    }
-                }
-            }
-            Err(Error::ServiceNotPresent(name))
-        }
-        Err(e) => Err(e),
-    }
+    process_env().get_service_by_name(name)
 }
 
 /// Get's you a a list services from`VCAP_SERVICES` by their type
@@ -328,32 +478,62 @@ pub fn get_services_by_type<T>(type_name: &str) -> Result<Vec<Service<T>>, Error
 where
     T: DeserializeOwned,
 {
-    match get_services() {
-        Ok(services) => {
-            if services.contains_key(type_name) {
-                let service_json = serde_json::to_string(services.get(type_name).unwrap()).unwrap();
-                match serde_json::from_str::<Vec<Service<T>>>(&service_json) {
-                    Ok(service) => return Ok(service),
-                    Err(_err) => {
-                        return Err(Error::JsonMalformed(format!("<{type_name}>.credentials")))
-                    }
-                }
-            }
-            Err(Error::ServiceTypeNotPresent(type_name))
-        }
-        Err(e) => Err(e),
-    }
+    process_env().get_services_by_type(type_name)
+}
+
+/// Get's you all services from `VCAP_SERVICES` matching the query, see [`ServiceQuery`]
+pub fn find_services<T>(query: &ServiceQuery) -> Result<Vec<Service<T>>, Error>
+where
+    T: DeserializeOwned,
+{
+    process_env().find_services(query)
+}
+
+/// Get's you the only service from `VCAP_SERVICES` matching the query, see [`ServiceQuery`]
+///
+/// Fails with [`Error::ServiceQueryNoMatch`] if no service and with [`Error::ServiceAmbiguous`] if multiple services match.
+pub fn find_service<T>(query: &ServiceQuery) -> Result<Service<T>, Error>
+where
+    T: DeserializeOwned,
+{
+    process_env().find_service(query)
+}
+
+/// Get's the platform endpoints from `VCAP_PLATFORM_OPTIONS` as typed PlatformOptions
+///
+/// Options this crate doesn't know of are kept in `extra`.
+///
+/// ```no_run
+/// let options = cf_env::get_platform_options().unwrap();
+///
+/// if let Some(credhub_uri) = options.credhub_uri {
+///     println!("CredHub is at {credhub_uri}");
+/// }
+/// ```
+pub fn get_platform_options() -> Result<PlatformOptions, Error> {
+    process_env().get_platform_options()
 }
 
 /// Get's you the information from `VCAP_APPLICATION` as a typed Application
-pub fn get_application_info() -> Result<Application, Error<'static>> {
-    match env::var(VCAP_APPLICATION) {
-        Ok(application) => match serde_json::from_str::<Application>(&application) {
-            Ok(value) => Ok(value),
-            Err(_err) => Err(Error::JsonMalformed(VCAP_APPLICATION.to_string())),
-        },
-        Err(_) => Err(Error::EnvNotSet(VCAP_APPLICATION)),
-    }
+pub fn get_application_info() -> Result<Application, Error> {
+    process_env().get_application_info()
+}
+
+/// Get's you the information from `VCAP_APPLICATION` with every field optional
+///
+/// Older and newer foundations send different fields, this works on all of them and keeps unknown fields in `extra`.
+///
+/// ```no_run
+/// use cf_env::ApplicationSchema;
+///
+/// let application = cf_env::get_application_lenient().unwrap();
+///
+/// if application.schema() == ApplicationSchema::Dea {
+///     println!("running on a DEA as instance {:?}", application.instance_index);
+/// }
+/// ```
+pub fn get_application_lenient() -> Result<LenientApplication, Error> {
+    process_env().get_application_lenient()
 }
 
 #[cfg(test)]
@@ -709,7 +889,7 @@ mod tests {
 
     #[test]
     fn get_memory_limit_invalid_unit() {
-        std::env::set_var("MEMORY_LIMIT", "512K");
+        std::env::set_var("MEMORY_LIMIT", "512X");
         let memory_limit_result = crate::get_memory_limit();
 
         assert!(memory_limit_result.is_err());
@@ -0,0 +1,269 @@
+use crate::constants::LANG;
+use crate::enums::Error;
+use locale_types::{Locale, LocaleIdentifier, LocaleString};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+/// A POSIX locale like `en_US.UTF-8@euro`, see [`crate::get_locale`]
+///
+/// Made of `language[_territory][.codeset][@modifier]`. The `C` and `POSIX` locales have no territory but may have a codeset, e.g. `C.UTF-8`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PosixLocale {
+    /// The ISO 639 language code, or `C` or `POSIX`
+    pub language: String,
+    /// The ISO 3166 country code or UN M.49 region number
+    pub territory: Option<String>,
+    pub codeset: Option<String>,
+    pub modifier: Option<String>,
+}
+
+impl PosixLocale {
+    /// Parses `input`, reporting errors for `variable_name`
+    pub fn parse(variable_name: &str, input: &str) -> Result<Self, Error> {
+        let malformed = |comment: &str| {
+            Error::EnvMalformed(
+                variable_name.to_string(),
+                format!("{input:?} isn't a valid locale, {comment}"),
+            )
+        };
+
+        let (rest, modifier) = match input.trim().split_once('@') {
+            Some((rest, modifier)) => (rest, Some(modifier)),
+            None => (input.trim(), None),
+        };
+        let (rest, codeset) = match rest.split_once('.') {
+            Some((rest, codeset)) => (rest, Some(codeset)),
+            None => (rest, None),
+        };
+        let (language, territory) = match rest.split_once('_') {
+            Some((language, territory)) => (language, Some(territory)),
+            None => (rest, None),
+        };
+
+        let is_posix = language == "C" || language == "POSIX";
+        let letters = (2..=3).contains(&language.len())
+            && language.bytes().all(|byte| byte.is_ascii_alphabetic());
+        if !(is_posix || letters) {
+            return Err(malformed("the language has to be two or three letters"));
+        }
+        if let Some(territory) = territory {
+            let letters =
+                territory.len() == 2 && territory.bytes().all(|byte| byte.is_ascii_alphabetic());
+            let digits =
+                territory.len() == 3 && territory.bytes().all(|byte| byte.is_ascii_digit());
+            if is_posix || !(letters || digits) {
+                return Err(malformed(
+                    "the territory has to be two letters or three digits",
+                ));
+            }
+        }
+        if codeset.is_some_and(|codeset| !is_identifier(codeset)) {
+            return Err(malformed("the codeset is invalid"));
+        }
+        if modifier.is_some_and(|modifier| !is_identifier(modifier)) {
+            return Err(malformed("the modifier is invalid"));
+        }
+
+        Ok(Self {
+            language: language.to_string(),
+            territory: territory.map(str::to_string),
+            codeset: codeset.map(str::to_string),
+            modifier: modifier.map(str::to_string),
+        })
+    }
+
+    /// Whether it's the `C` or `POSIX` locale, which means no localization
+    pub fn is_posix(&self) -> bool {
+        self.language == "C" || self.language == "POSIX"
+    }
+
+    /// Whether the codeset is UTF-8, in any of its spellings like `utf8`
+    pub fn is_utf8(&self) -> bool {
+        self.codeset.as_deref().is_some_and(|codeset| {
+            codeset
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .collect::<String>()
+                .eq_ignore_ascii_case("utf8")
+        })
+    }
+
+    /// The language and territory as BCP 47 tag, e.g. `en-US`, `None` for `C` and `POSIX`
+    pub fn language_tag(&self) -> Option<String> {
+        if self.is_posix() {
+            return None;
+        }
+        Some(match &self.territory {
+            Some(territory) => format!("{}-{territory}", self.language),
+            None => self.language.clone(),
+        })
+    }
+
+    /// Converts into a [`Locale`] of `locale_types`, `None` for the ones it can't represent like `C.UTF-8` or three letter languages
+    pub fn to_locale(&self) -> Option<Locale> {
+        if self.is_posix() {
+            return match self.codeset {
+                None => Some(Locale::POSIX),
+                Some(_) => None,
+            };
+        }
+        let mut locale = LocaleString::new(self.language.clone()).ok()?;
+        if let Some(territory) = &self.territory {
+            locale = locale.with_territory(territory.clone()).ok()?;
+        }
+        if let Some(codeset) = &self.codeset {
+            locale = locale.with_code_set(codeset.clone()).ok()?;
+        }
+        if let Some(modifier) = &self.modifier {
+            locale = locale.with_modifier(modifier.clone()).ok()?;
+        }
+        Some(Locale::String(locale))
+    }
+}
+
+fn is_identifier(input: &str) -> bool {
+    !input.is_empty()
+        && input
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')
+}
+
+impl FromStr for PosixLocale {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Self::parse(LANG, input)
+    }
+}
+
+impl Display for PosixLocale {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        write!(formatter, "{}", self.language)?;
+        if let Some(territory) = &self.territory {
+            write!(formatter, "_{territory}")?;
+        }
+        if let Some(codeset) = &self.codeset {
+            write!(formatter, ".{codeset}")?;
+        }
+        if let Some(modifier) = &self.modifier {
+            write!(formatter, "@{modifier}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CfEnv, PosixLocale};
+    use locale_types::Locale;
+    use std::collections::HashMap;
+
+    fn locale(input: &str) -> PosixLocale {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_parts() {
+        assert_eq!(
+            locale("en_US.UTF-8@euro"),
+            PosixLocale {
+                language: "en".to_string(),
+                territory: Some("US".to_string()),
+                codeset: Some("UTF-8".to_string()),
+                modifier: Some("euro".to_string()),
+            }
+        );
+        assert_eq!(locale("es_419").territory.as_deref(), Some("419"));
+        assert_eq!(locale("de@euro").modifier.as_deref(), Some("euro"));
+        assert_eq!(locale("de_CH").language_tag().as_deref(), Some("de-CH"));
+        assert!(locale("en_US.utf8").is_utf8());
+        assert!(!locale("en_US.ISO-8859-1").is_utf8());
+    }
+
+    #[test]
+    fn posix_locales() {
+        let c = locale("C.UTF-8");
+        assert!(c.is_posix());
+        assert!(c.is_utf8());
+        assert_eq!(c.language_tag(), None);
+        assert!(locale("POSIX").is_posix());
+        assert!("C_US".parse::<PosixLocale>().is_err());
+    }
+
+    #[test]
+    fn never_panics() {
+        for input in [
+            "",
+            "hello",
+            "_",
+            ".",
+            "@",
+            "en_",
+            "en.",
+            "en@",
+            "en_USA",
+            "e_US",
+            "en_US.UTF 8",
+            "ü_DE",
+            "en_ÜS",
+            "@@",
+            "C.@",
+        ] {
+            assert!(input.parse::<PosixLocale>().is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn into_locale() {
+        for input in ["en_US.UTF-8@euro", "de_CH", "fr"] {
+            assert_eq!(locale(input).to_locale().unwrap().to_string(), input);
+        }
+        assert_eq!(locale("C").to_locale(), Some(Locale::POSIX));
+        for input in ["C.UTF-8", "ast_ES.UTF-8", "es_419"] {
+            assert_eq!(locale(input).to_locale(), None, "{input}");
+        }
+        let env = CfEnv::new(HashMap::from([("LANG", "ast_ES.UTF-8")]));
+        assert!(env.get_lang().is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for input in ["en_US.UTF-8@euro", "C.UTF-8", "de_CH", "fr"] {
+            assert_eq!(locale(input).to_string(), input);
+        }
+    }
+
+    #[test]
+    fn fallback_order() {
+        let env = CfEnv::new(HashMap::from([
+            ("LC_ALL", ""),
+            ("LC_MESSAGES", "de_CH.UTF-8"),
+            ("LANG", "en_US.UTF-8"),
+        ]));
+        assert_eq!(env.get_locale().unwrap(), locale("de_CH.UTF-8"));
+
+        let env = CfEnv::new(HashMap::from([
+            ("LC_ALL", "C"),
+            ("LC_MESSAGES", "de_CH.UTF-8"),
+        ]));
+        assert!(env.get_locale().unwrap().is_posix());
+
+        let env = CfEnv::new(HashMap::from([("LANG", "hello")]));
+        assert_eq!(
+            env.get_locale().unwrap_err(),
+            crate::Error::EnvMalformed(
+                "LANG".to_string(),
+                "\"hello\" isn't a valid locale, the language has to be two or three letters"
+                    .to_string()
+            )
+        );
+
+        assert_eq!(
+            CfEnv::new(HashMap::<String, String>::new())
+                .get_locale()
+                .unwrap_err(),
+            crate::Error::EnvNotSet("LANG".to_string())
+        );
+    }
+}
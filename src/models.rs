@@ -1,32 +1,195 @@
-use crate::enums::{ByteUnit, Error};
+use crate::connection::ConnectionUri;
+use crate::constants::{MEMORY_LIMIT, VCAP_SERVICES};
+use crate::enums::{parse_json_value, ByteUnit, Error, JsonError};
+use crate::route::{parse_routes, primary_external_url, Route};
+use crate::timestamp::Timestamp;
 use guid_create::GUID;
+use http::Uri;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::hash::{Hash, Hasher};
+use std::ops::{Add, Div, Mul, Sub};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+/// A memory size like `MEMORY_LIMIT`, e.g. `512M`
+///
+/// Limits compare, hash and calculate by their number of bytes, `1G` equals `1024M`. Results of calculations use the largest unit they are a whole multiple of, so [`Display`] round-trips through [`MemoryLimit::from_string`].
+///
+/// ```
+/// use cf_env::MemoryLimit;
+///
+/// let limit: MemoryLimit = "1G".parse().unwrap();
+/// let cache = limit / 4;
+///
+/// assert_eq!(cache.to_string(), "256M");
+/// assert_eq!(cache.as_bytes(), 256 * 1024 * 1024);
+/// ```
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
 pub struct MemoryLimit {
     pub unit: ByteUnit,
     pub size: u128,
 }
 
 impl MemoryLimit {
-    pub fn from_string(mut input: String, env_variable: String) -> Result<Self, Error<'static>> {
-        match ByteUnit::from_string(input.clone()) {
-            Ok(unit) => {
-                input.pop();
-                match input.parse::<u128>() {
-                    Ok(size) => Ok(Self { unit, size }),
-                    Err(_) => Err(Error::EnvMalformed(
-                        env_variable,
-                        "Ins't a valid u128".to_string(),
-                    )),
-                }
-            }
-            Err(_) => Err(Error::UnknownMemoryUnit),
+    /// Parses `<size><unit>`, where the unit is one of `K`, `M`, `G` or `T` in any case and optionally followed by `B` or `iB`, or just `B`
+    pub fn from_string(input: String, env_variable: String) -> Result<Self, Error> {
+        let (size, unit) = ByteUnit::split(input.trim())?;
+        match size.trim().parse::<u128>() {
+            Ok(size) => Ok(Self { unit, size }),
+            Err(_) => Err(Error::EnvMalformed(
+                env_variable,
+                "Ins't a valid u128".to_string(),
+            )),
+        }
+    }
+
+    /// The limit in the largest unit `bytes` is a whole multiple of
+    pub fn from_bytes(bytes: u128) -> Self {
+        let unit = ByteUnit::largest_fitting(bytes);
+        Self {
+            unit,
+            size: bytes / unit.bytes(),
+        }
+    }
+
+    pub fn as_bytes(&self) -> u128 {
+        self.size.saturating_mul(self.unit.bytes())
+    }
+
+    /// Subtracts `other`, `None` if it's larger
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        Some(Self::from_bytes(
+            self.as_bytes().checked_sub(other.as_bytes())?,
+        ))
+    }
+
+    /// The given fraction of the limit, rounded down to whole bytes, e.g. `0.25` for a quarter
+    pub fn fraction(self, fraction: f64) -> Self {
+        Self::from_bytes((self.as_bytes() as f64 * fraction.max(0.0)) as u128)
+    }
+}
+
+/// Zero bytes
+impl Default for MemoryLimit {
+    fn default() -> Self {
+        Self {
+            unit: ByteUnit::Byte,
+            size: 0,
         }
     }
 }
 
+impl FromStr for MemoryLimit {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Self::from_string(input.to_string(), MEMORY_LIMIT.to_string())
+    }
+}
+
+impl Display for MemoryLimit {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        write!(formatter, "{}{}", self.size, self.unit)
+    }
+}
+
+impl PartialEq for MemoryLimit {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl Eq for MemoryLimit {}
+
+impl PartialOrd for MemoryLimit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MemoryLimit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_bytes().cmp(&other.as_bytes())
+    }
+}
+
+impl Hash for MemoryLimit {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_bytes().hash(state);
+    }
+}
+
+impl Add for MemoryLimit {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::from_bytes(self.as_bytes().saturating_add(other.as_bytes()))
+    }
+}
+
+/// Saturates at zero, use [`MemoryLimit::checked_sub`] to notice
+impl Sub for MemoryLimit {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::from_bytes(self.as_bytes().saturating_sub(other.as_bytes()))
+    }
+}
+
+impl Mul<u128> for MemoryLimit {
+    type Output = Self;
+
+    fn mul(self, factor: u128) -> Self {
+        Self::from_bytes(self.as_bytes().saturating_mul(factor))
+    }
+}
+
+/// Rounds down to whole bytes
+impl Div<u128> for MemoryLimit {
+    type Output = Self;
+
+    fn div(self, divisor: u128) -> Self {
+        Self::from_bytes(self.as_bytes() / divisor)
+    }
+}
+
+/// A single entry of `CF_INSTANCE_PORTS`
+///
+/// The tls proxy ports are only present if the platform runs the envoy proxy in front of the app.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InstancePortMapping {
+    pub external: Option<u16>,
+    pub internal: u16,
+    pub external_tls_proxy: Option<u16>,
+    pub internal_tls_proxy: Option<u16>,
+}
+
+/// The port mappings from `CF_INSTANCE_PORTS`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct InstancePorts(pub Vec<InstancePortMapping>);
+
+impl InstancePorts {
+    /// Get's the mapping of the port the app listens on
+    pub fn by_internal(&self, internal: u16) -> Option<&InstancePortMapping> {
+        self.0.iter().find(|mapping| mapping.internal == internal)
+    }
+
+    /// Get's the externally reachable port for the port the app listens on, preferring the tls proxy port
+    pub fn external_for(&self, internal: u16) -> Option<u16> {
+        self.by_internal(internal)
+            .and_then(|mapping| mapping.external_tls_proxy.or(mapping.external))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &InstancePortMapping> {
+        self.0.iter()
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct ApplicationLimits {
     pub disk: u128,
@@ -49,14 +212,56 @@ pub struct Application {
     pub organization_name: String,
     pub space_id: GUID,
     pub space_name: String,
-    pub start: Option<String>,
-    pub started_at: Option<String>,
-    pub started_at_timestamp: Option<String>,
-    pub state_timestamp: Option<String>,
+    pub start: Option<Timestamp>,
+    pub started_at: Option<Timestamp>,
+    pub started_at_timestamp: Option<Timestamp>,
+    pub state_timestamp: Option<Timestamp>,
     pub uris: Vec<String>,
     pub version: GUID,
 }
 
+impl Application {
+    /// When the instance was started, from whichever of `started_at_timestamp`, `started_at` and `start` is set
+    pub fn started(&self) -> Option<Timestamp> {
+        self.started_at_timestamp.or(self.started_at).or(self.start)
+    }
+
+    /// Time the instance is running for until `now`
+    pub fn uptime_at(&self, now: SystemTime) -> Option<Duration> {
+        self.started()?.elapsed_at(now)
+    }
+
+    /// Time the instance is running for, `None` if the platform didn't tell when it was started
+    pub fn uptime(&self) -> Option<Duration> {
+        self.uptime_at(SystemTime::now())
+    }
+
+    /// Parses the routes of `application_uris`, or `uris` on older foundations
+    pub fn routes(&self) -> Result<Vec<Route>, Error> {
+        parse_routes(&self.application_uris, &self.uris)
+    }
+
+    /// The url of the first route reachable from outside, neither internal nor TCP, e.g. for redirect uris and self links
+    pub fn primary_external_url(&self, scheme: &str) -> Result<Uri, Error> {
+        primary_external_url(&self.routes()?, scheme)
+    }
+}
+
+/// The platform endpoints of `VCAP_PLATFORM_OPTIONS`, see [`crate::get_platform_options`]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct PlatformOptions {
+    /// The CredHub credential references are resolved with
+    #[serde(
+        rename = "credhub-uri",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub credhub_uri: Option<String>,
+    /// The options this crate doesn't know of yet
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct ServiceVolumeMount {
     pub container_dir: String,
@@ -78,3 +283,97 @@ pub struct Service<Credentials = Value> {
     pub syslog_drain_url: Option<String>,
     pub volume_mounts: Vec<ServiceVolumeMount>,
 }
+
+impl Service<Value> {
+    /// Converts the generic credentials into the typed credentials `T`
+    ///
+    /// Goes straight from the already parsed json value, there is no serializing back into a string involved.
+    pub fn with_credentials<T>(&self) -> Result<Service<T>, JsonError>
+    where
+        T: DeserializeOwned,
+    {
+        Ok(Service {
+            binding_guid: self.binding_guid,
+            binding_name: self.binding_name.clone(),
+            instance_guid: self.instance_guid,
+            instance_name: self.instance_name.clone(),
+            name: self.name.clone(),
+            label: self.label.clone(),
+            tags: self.tags.clone(),
+            plan: self.plan.clone(),
+            credentials: parse_json_value(&self.credentials)?,
+            syslog_drain_url: self.syslog_drain_url.clone(),
+            volume_mounts: self.volume_mounts.clone(),
+        })
+    }
+
+    /// Parses `uri`, or `url` if there is none, of the credentials as ConnectionUri
+    pub fn connection_uri(&self) -> Result<ConnectionUri, Error> {
+        match ["uri", "url"]
+            .iter()
+            .find_map(|key| self.credentials.get(key)?.as_str())
+        {
+            Some(uri) => {
+                ConnectionUri::parse(VCAP_SERVICES, uri).map_err(|err| err.in_service(&self.name))
+            }
+            None => Err(Error::ServiceMalformed(
+                self.name.clone(),
+                "Has no uri in its credentials".to_string(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ByteUnit, MemoryLimit};
+
+    fn limit(input: &str) -> MemoryLimit {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn memory_limit_as_bytes() {
+        assert_eq!(limit("512K").as_bytes(), 512 * 1024);
+        assert_eq!(limit("512MB").as_bytes(), 512 * 1024 * 1024);
+        assert_eq!(limit("2GiB").as_bytes(), 2 << 30);
+        assert_eq!(limit("1t").as_bytes(), 1 << 40);
+        assert_eq!(limit("100B").as_bytes(), 100);
+        assert!("".parse::<MemoryLimit>().is_err());
+        assert!("M".parse::<MemoryLimit>().is_err());
+    }
+
+    #[test]
+    fn memory_limit_compares_bytes() {
+        assert_eq!(limit("1G"), limit("1024M"));
+        assert!(limit("1G") > limit("1000M"));
+        assert_eq!(
+            [limit("2G"), limit("512M"), limit("1T")].iter().max(),
+            Some(&limit("1T"))
+        );
+    }
+
+    #[test]
+    fn memory_limit_arithmetic() {
+        let total = limit("1G");
+
+        assert_eq!(total / 4, limit("256M"));
+        assert_eq!((total / 4).unit, ByteUnit::Megabyte);
+        assert_eq!(total - limit("512M"), limit("512M"));
+        assert_eq!(total + limit("1G"), limit("2G"));
+        assert_eq!((total * 2).unit, ByteUnit::Gigabyte);
+        assert_eq!(total.fraction(0.5), limit("512M"));
+        assert_eq!(limit("512M") - total, limit("0B"));
+        assert_eq!(limit("512M").checked_sub(total), None);
+    }
+
+    #[test]
+    fn memory_limit_display_round_trips() {
+        for input in ["512M", "1G", "3K", "1T", "100B"] {
+            assert_eq!(limit(input).to_string(), input);
+        }
+        assert_eq!((limit("1G") - limit("1K")).to_string(), "1048575K");
+        assert_eq!(limit("1536M").to_string(), "1536M");
+        assert_eq!(MemoryLimit::from_bytes(1536 << 20).to_string(), "1536M");
+    }
+}
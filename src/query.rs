@@ -0,0 +1,334 @@
+use crate::enums::Error;
+use crate::index::ServiceIndex;
+use crate::models::Service;
+use serde::de::DeserializeOwned;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// Finds services by their metadata instead of their exact name
+///
+/// All given criteria have to match. This allows portable code to e.g. pick the service tagged `postgres` no matter which broker provides it.
+///
+/// ```no_run
+/// use cf_env::ServiceQuery;
+///
+/// let query = ServiceQuery::new().tag("postgres").plan("small");
+///
+/// let service = cf_env::find_service::<serde_json::Value>(&query).unwrap();
+/// let all = cf_env::find_services::<serde_json::Value>(&ServiceQuery::new().name_pattern("orders-*")).unwrap();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ServiceQuery {
+    tags: Vec<String>,
+    label: Option<String>,
+    plan: Option<String>,
+    instance_name: Option<String>,
+    binding_name: Option<String>,
+    name_pattern: Option<String>,
+}
+
+impl ServiceQuery {
+    /// A query matching every service
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires the service to be tagged with `tag`, can be given multiple times
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn plan(mut self, plan: impl Into<String>) -> Self {
+        self.plan = Some(plan.into());
+        self
+    }
+
+    pub fn instance_name(mut self, instance_name: impl Into<String>) -> Self {
+        self.instance_name = Some(instance_name.into());
+        self
+    }
+
+    pub fn binding_name(mut self, binding_name: impl Into<String>) -> Self {
+        self.binding_name = Some(binding_name.into());
+        self
+    }
+
+    /// Requires the name to match `pattern`, where `*` matches any number of characters and `?` a single one
+    pub fn name_pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.name_pattern = Some(pattern.into());
+        self
+    }
+
+    /// Whether `service` matches all criteria of the query
+    pub fn matches<T>(&self, service: &Service<T>) -> bool {
+        self.tags.iter().all(|tag| service.tags.contains(tag))
+            && self
+                .label
+                .as_ref()
+                .map_or(true, |label| *label == service.label)
+            && self
+                .plan
+                .as_ref()
+                .map_or(true, |plan| *plan == service.plan)
+            && self.instance_name.as_ref().map_or(true, |instance_name| {
+                *instance_name == service.instance_name
+            })
+            && self.binding_name.as_ref().map_or(true, |binding_name| {
+                Some(binding_name) == service.binding_name.as_ref()
+            })
+            && self
+                .name_pattern
+                .as_ref()
+                .map_or(true, |pattern| glob_matches(pattern, &service.name))
+    }
+
+    /// Get's all matching services of `index`
+    pub fn all<'a>(&self, index: &'a ServiceIndex) -> Vec<&'a Service> {
+        index
+            .iter()
+            .filter(|service| self.matches(*service))
+            .collect()
+    }
+
+    /// Get's the only matching service of `index`
+    ///
+    /// Fails with [`Error::ServiceQueryNoMatch`] if none and with [`Error::ServiceAmbiguous`] if more than one service matches.
+    pub fn one<'a>(&self, index: &'a ServiceIndex) -> Result<&'a Service, Error> {
+        let mut matches = self.all(index);
+        match matches.len() {
+            0 => Err(Error::ServiceQueryNoMatch(self.to_string())),
+            1 => Ok(matches.remove(0)),
+            _ => Err(Error::ServiceAmbiguous(
+                self.to_string(),
+                matches
+                    .into_iter()
+                    .map(|service| service.name.clone())
+                    .collect(),
+            )),
+        }
+    }
+
+    /// Get's all matching services of `index` with typed credentials
+    pub fn all_typed<T: DeserializeOwned>(
+        &self,
+        index: &ServiceIndex,
+    ) -> Result<Vec<Service<T>>, Error> {
+        self.all(index).into_iter().map(typed).collect()
+    }
+
+    /// Get's the only matching service of `index` with typed credentials
+    pub fn one_typed<T: DeserializeOwned>(
+        &self,
+        index: &ServiceIndex,
+    ) -> Result<Service<T>, Error> {
+        typed(self.one(index)?)
+    }
+}
+
+impl Display for ServiceQuery {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        let mut criteria = self
+            .tags
+            .iter()
+            .map(|tag| format!("tag={tag}"))
+            .collect::<Vec<_>>();
+        let fields = [
+            ("label", &self.label),
+            ("plan", &self.plan),
+            ("instance_name", &self.instance_name),
+            ("binding_name", &self.binding_name),
+            ("name", &self.name_pattern),
+        ];
+        for (field, value) in fields {
+            if let Some(value) = value {
+                criteria.push(format!("{field}={value}"));
+            }
+        }
+
+        if criteria.is_empty() {
+            write!(formatter, "*")
+        } else {
+            write!(formatter, "{}", criteria.join(", "))
+        }
+    }
+}
+
+fn typed<T: DeserializeOwned>(service: &Service) -> Result<Service<T>, Error> {
+    match service.with_credentials::<T>() {
+        Ok(service) => Ok(service),
+        Err(err) => Err(Error::JsonMalformed(
+            format!("{}.credentials", service.name),
+            err,
+        )),
+    }
+}
+
+/// Matches `text` against `pattern` with `*` and `?` wildcards
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CfEnv, ServiceQuery};
+    use std::collections::HashMap;
+
+    const SERVICE_DATA: &str = r#"
+    {
+        "postgres": [
+          {
+            "label": "postgres",
+            "plan": "small",
+            "name": "orders-db",
+            "tags": ["postgres", "relational"],
+            "instance_guid": "2b6e08f9-3174-46ff-999d-183dc4c4964d",
+            "instance_name": "orders-db-instance",
+            "binding_guid": "3290823d-ab9f-4d72-b414-2438144ea9dc",
+            "binding_name": "orders",
+            "credentials": { "uri": "postgres://u:p@orders:5432/orders" },
+            "syslog_drain_url": null,
+            "volume_mounts": []
+          },
+          {
+            "label": "postgres",
+            "plan": "large",
+            "name": "users-db",
+            "tags": ["postgres", "relational"],
+            "instance_guid": "720a4210-3ea0-44e0-b3e3-63ad833191a9",
+            "instance_name": "users-db-instance",
+            "binding_guid": "8d2b186f-22a6-48a8-bb38-df5320987812",
+            "binding_name": null,
+            "credentials": { "uri": "postgres://u:p@users:5432/users" },
+            "syslog_drain_url": null,
+            "volume_mounts": []
+          }
+        ],
+        "elephantsql": [
+          {
+            "label": "elephantsql",
+            "plan": "small",
+            "name": "reports-db",
+            "tags": ["postgres"],
+            "instance_guid": "046463bc-1ba9-4046-bf5a-bd95672ee871",
+            "instance_name": "reports-db",
+            "binding_guid": "26421037-ab23-4b51-99f8-9f5a6251fd26",
+            "binding_name": null,
+            "credentials": { "uri": "postgres://u:p@reports:5432/reports" },
+            "syslog_drain_url": null,
+            "volume_mounts": []
+          }
+        ]
+    }"#;
+
+    fn env() -> CfEnv<HashMap<&'static str, &'static str>> {
+        CfEnv::new(HashMap::from([("VCAP_SERVICES", SERVICE_DATA)]))
+    }
+
+    fn names(services: Vec<crate::Service>) -> Vec<String> {
+        services.into_iter().map(|service| service.name).collect()
+    }
+
+    #[test]
+    fn matches_across_labels() {
+        let services = env()
+            .find_services::<serde_json::Value>(&ServiceQuery::new().tag("postgres").plan("small"))
+            .unwrap();
+
+        assert_eq!(names(services), vec!["reports-db", "orders-db"]);
+    }
+
+    #[test]
+    fn one_match() {
+        let env = env();
+
+        let queries = [
+            ServiceQuery::new().label("postgres").plan("large"),
+            ServiceQuery::new().instance_name("users-db-instance"),
+            ServiceQuery::new().binding_name("orders"),
+            ServiceQuery::new().name_pattern("rep*-?b"),
+        ];
+        let expected = ["users-db", "users-db", "orders-db", "reports-db"];
+
+        for (query, expected) in queries.iter().zip(expected) {
+            assert_eq!(
+                env.find_service::<serde_json::Value>(query).unwrap().name,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn ambiguous_and_missing() {
+        let env = env();
+
+        let error = env
+            .find_service::<serde_json::Value>(&ServiceQuery::new().tag("relational"))
+            .unwrap_err();
+        assert_eq!(
+            error,
+            crate::Error::ServiceAmbiguous(
+                "tag=relational".to_string(),
+                vec!["orders-db".to_string(), "users-db".to_string()]
+            )
+        );
+        assert_eq!(
+            error.to_string(),
+            "services \"orders-db\", \"users-db\" all match \"tag=relational\", expected exactly one"
+        );
+
+        assert_eq!(
+            env.find_service::<serde_json::Value>(&ServiceQuery::new().tag("mysql"))
+                .unwrap_err(),
+            crate::Error::ServiceQueryNoMatch("tag=mysql".to_string())
+        );
+        assert!(env
+            .find_services::<serde_json::Value>(&ServiceQuery::new().tag("mysql"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn glob_patterns() {
+        use super::glob_matches;
+
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("orders-*", "orders-db"));
+        assert!(glob_matches("*-db", "orders-db"));
+        assert!(glob_matches("o*s*b", "orders-db"));
+        assert!(glob_matches("?rders-db", "orders-db"));
+        assert!(!glob_matches("orders-?", "orders-db"));
+        assert!(!glob_matches("users-*", "orders-db"));
+    }
+}
@@ -0,0 +1,332 @@
+//! Hot reloading of the rotating instance identity certificate, requires the `reload` feature
+//!
+//! Diego rotates the instance identity certificate roughly every 24 hours by rewriting the files at `CF_INSTANCE_CERT` and `CF_INSTANCE_KEY` in place. A [`ReloadingResolver`] hands the current certificate to rustls on every handshake and a [`CertificateWatcher`] polls the files to swap in new ones.
+use crate::constants::{CF_INSTANCE_CERT, CF_INSTANCE_KEY};
+use crate::enums::Error;
+use crate::identity::{parse_key, read, InstanceCertificate, InstanceIdentity};
+use crate::tls::crypto_provider;
+use rustls::client::ResolvesClientCert;
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{ClientConfig, RootCertStore, ServerConfig, SignatureScheme};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How often a [`CertificateWatcher`] checks the files by default
+pub const DEFAULT_RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Resolves the instance identity certificate for rustls servers and clients, swapping it when the files change
+#[derive(Debug)]
+pub struct ReloadingResolver {
+    certificate_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<Current>,
+    last_error: Mutex<Option<Error>>,
+}
+
+#[derive(Debug)]
+struct Current {
+    identity: InstanceIdentity,
+    certified_key: Arc<CertifiedKey>,
+    files: (Vec<u8>, Vec<u8>),
+}
+
+impl ReloadingResolver {
+    /// Loads the certificate chain and private key from the given files
+    pub fn new(
+        certificate_path: impl Into<PathBuf>,
+        key_path: impl Into<PathBuf>,
+    ) -> Result<Self, Error> {
+        let certificate_path = certificate_path.into();
+        let key_path = key_path.into();
+        let current = Current::load((
+            read(CF_INSTANCE_CERT, &certificate_path)?,
+            read(CF_INSTANCE_KEY, &key_path)?,
+        ))?;
+
+        Ok(Self {
+            certificate_path,
+            key_path,
+            current: RwLock::new(current),
+            last_error: Mutex::new(None),
+        })
+    }
+
+    /// Re-reads the files and swaps in the new certificate if they changed
+    ///
+    /// Returns whether the certificate was swapped. On failure, e.g. when only one of the files was rewritten yet, the current certificate is kept.
+    pub fn reload(&self) -> Result<bool, Error> {
+        let files = (
+            read(CF_INSTANCE_CERT, &self.certificate_path)?,
+            read(CF_INSTANCE_KEY, &self.key_path)?,
+        );
+        if self.current.read().unwrap().files == files {
+            return Ok(false);
+        }
+
+        let next = Current::load(files)?;
+        *self.current.write().unwrap() = next;
+        Ok(true)
+    }
+
+    /// The currently used instance identity
+    pub fn identity(&self) -> InstanceIdentity {
+        self.current.read().unwrap().identity.clone()
+    }
+
+    /// Takes the error of the latest reload of a [`CertificateWatcher`], `None` if it succeeded
+    pub fn take_last_error(&self) -> Option<Error> {
+        self.last_error.lock().unwrap().take()
+    }
+
+    fn certified_key(&self) -> Arc<CertifiedKey> {
+        self.current.read().unwrap().certified_key.clone()
+    }
+
+    /// Builds a server config using this resolver and requiring client certificates signed by one of `roots`
+    pub fn server_config(self: &Arc<Self>, roots: RootCertStore) -> Result<ServerConfig, Error> {
+        let provider = crypto_provider();
+        let verifier =
+            WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                .build()
+                .map_err(|err| malformed(err.to_string()))?;
+
+        Ok(ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(|err| malformed(err.to_string()))?
+            .with_client_cert_verifier(verifier)
+            .with_cert_resolver(self.clone()))
+    }
+
+    /// Builds a client config using this resolver for client certificates and trusting `roots`
+    pub fn client_config(self: &Arc<Self>, roots: RootCertStore) -> Result<ClientConfig, Error> {
+        Ok(ClientConfig::builder_with_provider(crypto_provider())
+            .with_safe_default_protocol_versions()
+            .map_err(|err| malformed(err.to_string()))?
+            .with_root_certificates(roots)
+            .with_client_cert_resolver(self.clone()))
+    }
+
+    /// Starts a [`CertificateWatcher`] checking the files every `interval`
+    pub fn watch(self: &Arc<Self>, interval: Duration) -> CertificateWatcher {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let resolver = self.clone();
+
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                // Held while reloading, so a swapped certificate is never seen next to the error of an earlier attempt
+                let mut last_error = resolver.last_error.lock().unwrap();
+                *last_error = resolver.reload().err();
+            }
+        });
+
+        CertificateWatcher {
+            resolver: self.clone(),
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+}
+
+impl ResolvesServerCert for ReloadingResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.certified_key())
+    }
+}
+
+impl ResolvesClientCert for ReloadingResolver {
+    fn resolve(
+        &self,
+        _root_hint_subjects: &[&[u8]],
+        _sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        Some(self.certified_key())
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+}
+
+/// Polls the instance identity files in a background thread, stops when dropped
+#[derive(Debug)]
+pub struct CertificateWatcher {
+    resolver: Arc<ReloadingResolver>,
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl CertificateWatcher {
+    pub fn resolver(&self) -> &Arc<ReloadingResolver> {
+        &self.resolver
+    }
+}
+
+impl Drop for CertificateWatcher {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Current {
+    fn load(files: (Vec<u8>, Vec<u8>)) -> Result<Self, Error> {
+        let identity = InstanceIdentity {
+            certificate: InstanceCertificate::from_pem(&files.0)?,
+            key: parse_key(&files.1)?,
+        };
+        let certified_key = CertifiedKey::from_der(
+            identity.certificate.chain.clone(),
+            identity.key.clone_key(),
+            &crypto_provider(),
+        )
+        .map_err(|err| malformed(err.to_string()))?;
+
+        Ok(Self {
+            identity,
+            certified_key: Arc::new(certified_key),
+            files,
+        })
+    }
+}
+
+fn malformed(comment: String) -> Error {
+    Error::EnvMalformed(CF_INSTANCE_CERT.to_string(), comment)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ReloadingResolver;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+    fn install(directory: &Path, name: &str) {
+        for extension in ["crt", "key"] {
+            install_file(directory, name, extension);
+        }
+    }
+
+    /// Renamed into place, so a reload never reads a half written file
+    fn install_file(directory: &Path, name: &str, extension: &str) {
+        let temporary = directory.join(format!("instance.{extension}.tmp"));
+        fs::copy(
+            Path::new(FIXTURES).join(format!("{name}.{extension}")),
+            &temporary,
+        )
+        .unwrap();
+        fs::rename(temporary, directory.join(format!("instance.{extension}"))).unwrap();
+    }
+
+    fn resolver(directory: &Path) -> Arc<ReloadingResolver> {
+        Arc::new(
+            ReloadingResolver::new(
+                directory.join("instance.crt"),
+                directory.join("instance.key"),
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn reload_swaps_certificate() {
+        let directory = tempfile::tempdir().unwrap();
+        install(directory.path(), "instance");
+        let resolver = resolver(directory.path());
+        let before = resolver.identity();
+
+        assert!(!resolver.reload().unwrap());
+
+        install(directory.path(), "rotated");
+        assert!(resolver.reload().unwrap());
+        assert_ne!(resolver.identity().certificate, before.certificate);
+    }
+
+    #[test]
+    fn reload_keeps_certificate_on_mismatch() {
+        let directory = tempfile::tempdir().unwrap();
+        install(directory.path(), "instance");
+        let resolver = resolver(directory.path());
+        let before = resolver.identity();
+
+        install_file(directory.path(), "rotated", "crt");
+
+        assert!(resolver.reload().is_err());
+        assert_eq!(resolver.identity(), before);
+    }
+
+    #[test]
+    fn watcher_picks_up_rotation() {
+        let directory = tempfile::tempdir().unwrap();
+        install(directory.path(), "instance");
+        let watcher = resolver(directory.path()).watch(Duration::from_millis(10));
+        let before = watcher.resolver().identity();
+
+        install(directory.path(), "rotated");
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while watcher.resolver().identity() == before {
+            assert!(Instant::now() < deadline, "certificate wasn't reloaded");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(watcher.resolver().take_last_error().is_none());
+    }
+
+    #[test]
+    fn watcher_clears_error_after_successful_reload() {
+        let directory = tempfile::tempdir().unwrap();
+        install(directory.path(), "instance");
+        let watcher = resolver(directory.path()).watch(Duration::from_millis(10));
+        let before = watcher.resolver().identity();
+
+        // Only the certificate is rewritten yet, so the watcher keeps failing on the mismatch
+        install_file(directory.path(), "rotated", "crt");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while watcher.resolver().take_last_error().is_none() {
+            assert!(Instant::now() < deadline, "mismatch wasn't reported");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+
+        install_file(directory.path(), "rotated", "key");
+        while watcher.resolver().identity() == before {
+            assert!(Instant::now() < deadline, "certificate wasn't reloaded");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(watcher.resolver().take_last_error().is_none());
+    }
+
+    #[test]
+    fn handshake_uses_rotated_certificate() {
+        let directory = tempfile::tempdir().unwrap();
+        install(directory.path(), "instance");
+        let resolver = resolver(directory.path());
+        let roots = crate::tls::root_store(
+            crate::read_certificates_dir(Path::new(FIXTURES).join("system-certificates")).unwrap(),
+        )
+        .unwrap();
+        let server_config = Arc::new(resolver.server_config(roots.clone()).unwrap());
+        let client_config = Arc::new(resolver.client_config(roots).unwrap());
+
+        install(directory.path(), "rotated");
+        resolver.reload().unwrap();
+
+        let mut server = rustls::ServerConnection::new(server_config).unwrap();
+        let mut client =
+            rustls::ClientConnection::new(client_config, "localhost".try_into().unwrap()).unwrap();
+        crate::tls::tests::handshake(&mut client, &mut server).unwrap();
+
+        assert_eq!(
+            &server.peer_certificates().unwrap()[0],
+            resolver.identity().certificate.leaf()
+        );
+    }
+}
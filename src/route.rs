@@ -0,0 +1,269 @@
+use crate::constants::VCAP_APPLICATION;
+use crate::enums::Error;
+use http::Uri;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+/// The domain Cloud Foundry uses for container to container routes by default
+pub const INTERNAL_DOMAIN: &str = "apps.internal";
+
+/// A route of `application_uris`, e.g. `backend.example.io/api` or `tcp.example.io:1024`
+///
+/// Cloud Foundry only sends the full hostname, so the first label is taken as host if there are at least three of them. `example.io` becomes a route to the domain `example.io` without a host, `backend.example.io` one with host `backend` on the domain `example.io`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Route {
+    pub host: Option<String>,
+    pub domain: String,
+    /// Only set for TCP routes
+    pub port: Option<u16>,
+    /// Starts with a `/` if set
+    pub path: Option<String>,
+}
+
+impl Route {
+    /// The full hostname, host and domain
+    pub fn hostname(&self) -> String {
+        match &self.host {
+            Some(host) => format!("{host}.{}", self.domain),
+            None => self.domain.clone(),
+        }
+    }
+
+    /// Whether it's a container to container route on `apps.internal`, which is only reachable from other apps
+    pub fn is_internal(&self) -> bool {
+        self.domain == INTERNAL_DOMAIN || self.domain.ends_with(".apps.internal")
+    }
+
+    /// Whether it's a TCP route, only those carry a port
+    pub fn is_tcp(&self) -> bool {
+        self.port.is_some()
+    }
+
+    /// Whether it's a HTTP route reachable from outside of the foundation
+    pub fn is_external_http(&self) -> bool {
+        !self.is_internal() && !self.is_tcp()
+    }
+
+    /// Builds the url to the route with the given scheme, e.g. `https://backend.example.io/api`
+    pub fn url(&self, scheme: &str) -> Result<Uri, Error> {
+        let url = format!("{scheme}://{self}");
+        match url.parse::<Uri>() {
+            Ok(url) => Ok(url),
+            Err(_) => Err(Error::EnvMalformed(
+                VCAP_APPLICATION.to_string(),
+                format!("{url:?} isn't a valid url"),
+            )),
+        }
+    }
+}
+
+impl FromStr for Route {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let malformed = |comment: &str| {
+            Error::EnvMalformed(
+                VCAP_APPLICATION.to_string(),
+                format!("Route {input:?} {comment}"),
+            )
+        };
+
+        let route = input.trim();
+        let route = match route.split_once("://") {
+            Some((_, route)) => route,
+            None => route,
+        };
+        let (authority, path) = match route.find('/') {
+            Some(index) => (&route[..index], Some(&route[index..])),
+            None => (route, None),
+        };
+        let (hostname, port) = match authority.rsplit_once(':') {
+            Some((hostname, port)) => match port.parse::<u16>() {
+                Ok(port) => (hostname, Some(port)),
+                Err(_) => return Err(malformed("has an invalid port")),
+            },
+            None => (authority, None),
+        };
+
+        if hostname.is_empty()
+            || hostname
+                .split('.')
+                .any(|label| label.is_empty() || label.contains(char::is_whitespace))
+        {
+            return Err(malformed("has an invalid hostname"));
+        }
+
+        let (host, domain) = match hostname.split_once('.') {
+            Some((host, domain)) if domain.contains('.') => (Some(host.to_string()), domain),
+            _ => (None, hostname),
+        };
+
+        Ok(Self {
+            host,
+            domain: domain.to_string(),
+            port,
+            path: path
+                .map(|path| path.trim_end_matches('/'))
+                .filter(|path| !path.is_empty())
+                .map(str::to_string),
+        })
+    }
+}
+
+/// Formats as Cloud Foundry does in `application_uris`
+impl Display for Route {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        write!(formatter, "{}", self.hostname())?;
+        if let Some(port) = self.port {
+            write!(formatter, ":{port}")?;
+        }
+        if let Some(path) = &self.path {
+            write!(formatter, "{path}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the routes of `application_uris`, falling back to the older `uris`
+pub(crate) fn parse_routes(
+    application_uris: &[String],
+    uris: &[String],
+) -> Result<Vec<Route>, Error> {
+    let uris = if application_uris.is_empty() {
+        uris
+    } else {
+        application_uris
+    };
+
+    let mut routes = Vec::with_capacity(uris.len());
+    for uri in uris {
+        let route = uri.parse::<Route>()?;
+        if !routes.contains(&route) {
+            routes.push(route);
+        }
+    }
+    Ok(routes)
+}
+
+/// The url of the first external HTTP route
+pub(crate) fn primary_external_url(routes: &[Route], scheme: &str) -> Result<Uri, Error> {
+    match routes.iter().find(|route| route.is_external_http()) {
+        Some(route) => route.url(scheme),
+        None => Err(Error::EnvMalformed(
+            VCAP_APPLICATION.to_string(),
+            "Doesn't contain an external http route".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CfEnv, Route};
+    use std::collections::HashMap;
+
+    fn route(input: &str) -> Route {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_routes() {
+        assert_eq!(
+            route("backend.example.io/api/v1/"),
+            Route {
+                host: Some("backend".to_string()),
+                domain: "example.io".to_string(),
+                port: None,
+                path: Some("/api/v1".to_string()),
+            }
+        );
+        assert_eq!(route("example.io").host, None);
+        assert_eq!(route("example.io").hostname(), "example.io");
+
+        let tcp = route("tcp.example.io:1024");
+        assert!(tcp.is_tcp());
+        assert_eq!(tcp.port, Some(1024));
+        assert!(!tcp.is_external_http());
+
+        let internal = route("backend.apps.internal");
+        assert!(internal.is_internal());
+        assert_eq!(internal.domain, "apps.internal");
+        assert!(route("backend.eu.apps.internal").is_internal());
+        assert!(!route("apps.internal.example.io").is_internal());
+    }
+
+    #[test]
+    fn rejects_invalid_routes() {
+        for input in ["", "example..io", "example.io:http", ":8080", "exa mple.io"] {
+            assert!(input.parse::<Route>().is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for input in [
+            "backend.example.io/api",
+            "tcp.example.io:1024",
+            "example.io",
+        ] {
+            assert_eq!(route(input).to_string(), input);
+        }
+        assert_eq!(
+            route("backend.example.io/api").url("https").unwrap(),
+            "https://backend.example.io/api"
+        );
+    }
+
+    #[test]
+    fn application_routes() {
+        let env = CfEnv::new(HashMap::from([(
+            "VCAP_APPLICATION",
+            r#"{
+                "cf_api": "https://api.example.io",
+                "limits": { "fds": 12384, "mem": 512, "disk": 1024 },
+                "application_name": "my-backend",
+                "application_uris": ["backend.apps.internal", "tcp.example.io:1024", "backend.example.io", "backend.example.io/api"],
+                "name": "my-backend",
+                "space_name": "production",
+                "space_id": "29349a46-ff0c-447e-bde0-db1be814f564",
+                "organization_id": "26421037-ab23-4b51-99f8-9f5a6251fd26",
+                "organization_name": "Project_One",
+                "uris": [],
+                "process_id": "d8304a62-2df7-41d5-9211-0917c2253591",
+                "process_type": "web",
+                "application_id": "d8304a62-2df7-41d5-9211-0917c2253591",
+                "version": "9fe9fe07-c7b7-415b-afa3-75fef5258d47",
+                "application_version": "9fe9fe07-c7b7-415b-afa3-75fef5258d47"
+            }"#,
+        )]));
+
+        let application = env.get_application_info().unwrap();
+        assert_eq!(application.routes().unwrap().len(), 4);
+        assert_eq!(
+            application.primary_external_url("https").unwrap(),
+            "https://backend.example.io/"
+        );
+        assert_eq!(
+            env.get_application_lenient()
+                .unwrap()
+                .primary_external_url("http")
+                .unwrap(),
+            "http://backend.example.io/"
+        );
+    }
+
+    #[test]
+    fn no_external_route() {
+        let routes = vec![route("backend.apps.internal")];
+
+        assert_eq!(
+            super::primary_external_url(&routes, "https")
+                .unwrap_err()
+                .kind(),
+            crate::ErrorKind::EnvMalformed
+        );
+        assert_eq!(
+            super::parse_routes(&[], &["a.example.io".to_string()]).unwrap(),
+            vec![route("a.example.io")]
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::env;
+use std::hash::{BuildHasher, Hash};
+
+/// A place environment variables are read from
+///
+/// Implemented for the process environment ([`ProcessEnv`]), any `HashMap` of string like keys and values and a frozen copy of the process environment ([`EnvSnapshot`]). Use the latter two in tests to not have to mutate the process environment.
+pub trait EnvSource {
+    /// Returns the value of the variable `key`, `None` if it's not set or not valid unicode
+    fn var(&self, key: &str) -> Option<String>;
+}
+
+impl<T: EnvSource + ?Sized> EnvSource for &T {
+    fn var(&self, key: &str) -> Option<String> {
+        (**self).var(key)
+    }
+}
+
+/// Reads the environment of the current process on every access
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProcessEnv;
+
+impl EnvSource for ProcessEnv {
+    fn var(&self, key: &str) -> Option<String> {
+        env::var(key).ok()
+    }
+}
+
+impl<K, V, H> EnvSource for HashMap<K, V, H>
+where
+    K: Borrow<str> + Hash + Eq,
+    V: AsRef<str>,
+    H: BuildHasher,
+{
+    fn var(&self, key: &str) -> Option<String> {
+        self.get(key).map(|value| value.as_ref().to_string())
+    }
+}
+
+/// A frozen copy of the process environment
+///
+/// Later changes to the process environment are not visible through a snapshot. Variables which aren't valid unicode are left out.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EnvSnapshot {
+    vars: HashMap<String, String>,
+}
+
+impl EnvSnapshot {
+    /// Copies all variables of the current process environment
+    pub fn capture() -> Self {
+        Self {
+            vars: env::vars_os()
+                .filter_map(|(key, value)| {
+                    Some((key.into_string().ok()?, value.into_string().ok()?))
+                })
+                .collect(),
+        }
+    }
+
+    /// Iterates over all captured variables
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.vars
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+}
+
+impl From<HashMap<String, String>> for EnvSnapshot {
+    fn from(vars: HashMap<String, String>) -> Self {
+        Self { vars }
+    }
+}
+
+impl FromIterator<(String, String)> for EnvSnapshot {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        Self {
+            vars: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl EnvSource for EnvSnapshot {
+    fn var(&self, key: &str) -> Option<String> {
+        self.vars.get(key).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::EnvSource;
+    use std::collections::HashMap;
+
+    #[test]
+    fn hash_map_source() {
+        let source = HashMap::from([("PORT", "8080")]);
+
+        assert_eq!(source.var("PORT"), Some("8080".to_string()));
+        assert_eq!(source.var("HOME"), None);
+    }
+
+    #[test]
+    fn snapshot_is_frozen() {
+        std::env::set_var("CF_ENV_SNAPSHOT_TEST", "before");
+        let snapshot = crate::EnvSnapshot::capture();
+        std::env::set_var("CF_ENV_SNAPSHOT_TEST", "after");
+
+        assert_eq!(
+            snapshot.var("CF_ENV_SNAPSHOT_TEST"),
+            Some("before".to_string())
+        );
+        assert_eq!(
+            crate::ProcessEnv.var("CF_ENV_SNAPSHOT_TEST"),
+            Some("after".to_string())
+        );
+        std::env::remove_var("CF_ENV_SNAPSHOT_TEST");
+    }
+
+    #[test]
+    fn snapshot_from_iter() {
+        let snapshot: crate::EnvSnapshot = [("USER".to_string(), "vcap".to_string())]
+            .into_iter()
+            .collect();
+
+        assert_eq!(snapshot.var("USER"), Some("vcap".to_string()));
+    }
+}
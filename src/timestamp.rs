@@ -0,0 +1,479 @@
+use crate::constants::VCAP_APPLICATION;
+use crate::enums::Error;
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// A point in time from `VCAP_APPLICATION`
+///
+/// Depending on the foundation timestamps are sent as `2013-08-12 00:05:29 +0000`, as RFC 3339 or as epoch seconds, either as number or as string. All of them are parsed into this, it serializes as RFC 3339 in UTC.
+///
+/// Convert it into a [`SystemTime`], or with the `time` and `chrono` features try to convert it into their types, which hold a smaller range of years.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Timestamp(SystemTime);
+
+impl Timestamp {
+    /// `None` if the seconds lie outside of what a [`SystemTime`] can hold
+    pub fn from_unix_seconds(seconds: i64) -> Option<Self> {
+        system_time(seconds, 0).map(Self)
+    }
+
+    pub fn as_system_time(&self) -> SystemTime {
+        self.0
+    }
+
+    /// Seconds since the unix epoch, negative before it
+    pub fn unix_seconds(&self) -> i64 {
+        match self.0.duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs() as i64,
+            Err(err) => -(err.duration().as_secs_f64().ceil() as i64),
+        }
+    }
+
+    /// Time passed from this timestamp until `now`, `None` if it lies in the future
+    pub fn elapsed_at(&self, now: SystemTime) -> Option<Duration> {
+        now.duration_since(self.0).ok()
+    }
+
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.elapsed_at(SystemTime::now())
+    }
+
+    #[cfg(any(feature = "time", feature = "chrono"))]
+    fn unix_nanos(&self) -> i128 {
+        match self.0.duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_nanos() as i128,
+            Err(err) => -(err.duration().as_nanos() as i128),
+        }
+    }
+
+    #[cfg(any(feature = "time", feature = "chrono"))]
+    fn out_of_range(&self, target: &str) -> Error {
+        Error::EnvMalformed(
+            VCAP_APPLICATION.to_string(),
+            format!("{self} is out of range for {target}"),
+        )
+    }
+}
+
+impl From<SystemTime> for Timestamp {
+    fn from(time: SystemTime) -> Self {
+        Self(time)
+    }
+}
+
+impl From<Timestamp> for SystemTime {
+    fn from(timestamp: Timestamp) -> Self {
+        timestamp.0
+    }
+}
+
+#[cfg(feature = "time")]
+impl TryFrom<Timestamp> for time::OffsetDateTime {
+    type Error = Error;
+
+    /// Fails for timestamps beyond the year 9999, which `time` can't hold
+    fn try_from(timestamp: Timestamp) -> Result<Self, Self::Error> {
+        Self::from_unix_timestamp_nanos(timestamp.unix_nanos())
+            .map_err(|_| timestamp.out_of_range("time::OffsetDateTime"))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TryFrom<Timestamp> for chrono::DateTime<chrono::Utc> {
+    type Error = Error;
+
+    /// Fails for timestamps beyond what `chrono` can hold, roughly the year 262143
+    fn try_from(timestamp: Timestamp) -> Result<Self, Self::Error> {
+        let nanos = timestamp.unix_nanos();
+        i64::try_from(nanos.div_euclid(1_000_000_000))
+            .ok()
+            .and_then(|seconds| {
+                Self::from_timestamp(seconds, nanos.rem_euclid(1_000_000_000) as u32)
+            })
+            .ok_or_else(|| timestamp.out_of_range("chrono::DateTime"))
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        let parsed = match input.parse::<f64>() {
+            Ok(seconds) if seconds.is_finite() => from_unix_seconds_f64(seconds),
+            _ => parse_date_time(input),
+        };
+
+        match parsed {
+            Some(timestamp) => Ok(timestamp),
+            None => Err(Error::EnvMalformed(
+                VCAP_APPLICATION.to_string(),
+                format!("{input:?} isn't a timestamp nor epoch seconds"),
+            )),
+        }
+    }
+}
+
+/// Formats as RFC 3339 in UTC, e.g. `2013-08-12T00:05:29Z`
+impl Display for Timestamp {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        let seconds = self.unix_seconds();
+        let (year, month, day) = civil_from_days(seconds.div_euclid(SECONDS_PER_DAY));
+        let time = seconds.rem_euclid(SECONDS_PER_DAY);
+
+        write!(
+            formatter,
+            "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}",
+            time / 3600,
+            time % 3600 / 60,
+            time % 60
+        )?;
+        let nanos = match self.0.duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.subsec_nanos(),
+            Err(_) => 0,
+        };
+        if nanos > 0 {
+            write!(
+                formatter,
+                ".{}",
+                format!("{nanos:09}").trim_end_matches('0')
+            )?;
+        }
+        write!(formatter, "Z")
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(TimestampVisitor)
+    }
+}
+
+struct TimestampVisitor;
+
+impl Visitor<'_> for TimestampVisitor {
+    type Value = Timestamp;
+
+    fn expecting(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        formatter.write_str("a timestamp or epoch seconds")
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+        Timestamp::from_unix_seconds(value).ok_or_else(|| out_of_range(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        match UNIX_EPOCH.checked_add(Duration::from_secs(value)) {
+            Some(time) => Ok(Timestamp(time)),
+            None => Err(out_of_range(value)),
+        }
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+        if !value.is_finite() {
+            return Err(E::custom("epoch seconds aren't finite"));
+        }
+        from_unix_seconds_f64(value).ok_or_else(|| out_of_range(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        value.parse().map_err(E::custom)
+    }
+}
+
+fn out_of_range<E: de::Error>(seconds: impl Display) -> E {
+    E::custom(format!("{seconds} epoch seconds are out of range"))
+}
+
+fn from_unix_seconds_f64(seconds: f64) -> Option<Timestamp> {
+    let whole = seconds.floor();
+    // `as` would saturate, so values beyond i64 are rejected up front
+    if whole < i64::MIN as f64 || whole >= i64::MAX as f64 {
+        return None;
+    }
+    let nanos = ((seconds - whole) * 1e9).round() as u32;
+    system_time(whole as i64, nanos.min(999_999_999)).map(Timestamp)
+}
+
+fn system_time(seconds: i64, nanos: u32) -> Option<SystemTime> {
+    let time = if seconds >= 0 {
+        UNIX_EPOCH.checked_add(Duration::from_secs(seconds.unsigned_abs()))
+    } else {
+        UNIX_EPOCH.checked_sub(Duration::from_secs(seconds.unsigned_abs()))
+    };
+    time?.checked_add(Duration::from_nanos(u64::from(nanos)))
+}
+
+/// Parses `YYYY-MM-DD HH:MM:SS[.fraction][ ]<offset>`, with a space or `T` between date and time and the offset being `Z`, `UTC`, `+HHMM` or `+HH:MM`
+fn parse_date_time(input: &str) -> Option<Timestamp> {
+    let (date, rest) = (input.get(..10)?, input.get(10..)?);
+    let rest = rest.strip_prefix([' ', 'T', 't'])?;
+    let (time, mut rest) = (rest.get(..8)?, rest.get(8..)?);
+    // Checked on bytes before anything is sliced, so multibyte characters can't split a boundary
+    let (date_bytes, time_bytes) = (date.as_bytes(), time.as_bytes());
+    if date_bytes[4] != b'-'
+        || date_bytes[7] != b'-'
+        || time_bytes[2] != b':'
+        || time_bytes[5] != b':'
+    {
+        return None;
+    }
+
+    let mut nanos = 0;
+    if let Some(fraction) = rest.strip_prefix(['.', ',']) {
+        let digits = fraction.len()
+            - fraction
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .len();
+        if digits == 0 {
+            return None;
+        }
+        let padded = format!("{:0<9}", &fraction[..digits.min(9)]);
+        nanos = padded.parse::<u32>().ok()?;
+        rest = &fraction[digits..];
+    }
+
+    let days = days_from_civil(
+        number(date, 0..4)?,
+        number(date, 5..7)?,
+        number(date, 8..10)?,
+    )?;
+    let (hours, minutes, seconds) = (
+        number(time, 0..2)?,
+        number(time, 3..5)?,
+        number(time, 6..8)?,
+    );
+    if hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+
+    let offset = parse_offset(rest.trim_start())?;
+    let seconds = days * SECONDS_PER_DAY + hours * 3600 + minutes * 60 + seconds - offset;
+    system_time(seconds, nanos).map(Timestamp)
+}
+
+/// Offset to UTC in seconds
+fn parse_offset(offset: &str) -> Option<i64> {
+    match offset {
+        "" | "Z" | "z" | "UTC" | "GMT" => return Some(0),
+        _ => {}
+    }
+    let sign = match offset.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let offset = offset[1..].replace(':', "");
+    if offset.len() != 4 {
+        return None;
+    }
+    let (hours, minutes) = (number(&offset, 0..2)?, number(&offset, 2..4)?);
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+fn number(input: &str, range: std::ops::Range<usize>) -> Option<i64> {
+    let digits = input.get(range)?;
+    if digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        digits.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Days since the unix epoch of a date in the proleptic gregorian calendar, see <https://howardhinnant.github.io/date_algorithms.html>
+fn days_from_civil(year: i64, month: i64, day: i64) -> Option<i64> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some(era * 146_097 + day_of_era - 719_468)
+}
+
+/// The inverse of [`days_from_civil`]
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CfEnv, Timestamp};
+    use std::collections::HashMap;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    const STARTED: i64 = 1_376_265_929;
+
+    #[test]
+    fn parses_formats() {
+        for input in [
+            "2013-08-12 00:05:29 +0000",
+            "2013-08-12 02:05:29 +0200",
+            "2013-08-11 22:05:29 -02:00",
+            "2013-08-12T00:05:29Z",
+            "2013-08-12 00:05:29",
+            "1376265929",
+        ] {
+            assert_eq!(
+                input.parse::<Timestamp>().unwrap().unix_seconds(),
+                STARTED,
+                "{input}"
+            );
+        }
+
+        let fractional = "2013-08-12T00:05:29.25Z".parse::<Timestamp>().unwrap();
+        assert_eq!(
+            fractional.as_system_time(),
+            UNIX_EPOCH + Duration::from_millis(1_376_265_929_250)
+        );
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        for input in [
+            "",
+            "yesterday",
+            "2013-13-12 00:05:29",
+            "2013-08-12 25:05:29",
+            "2013-08-12 00:05:29 CEST",
+            "2013-08-12 0é:05:29 +0000",
+            "2013é08-12 00:05:29 +0000",
+            "1e300",
+        ] {
+            assert!(input.parse::<Timestamp>().is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range() {
+        for input in ["18446744073709551615", "1e300", "-1e300"] {
+            assert!(serde_json::from_str::<Timestamp>(input).is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for input in [
+            "2013-08-12T00:05:29Z",
+            "1969-12-31T23:59:59Z",
+            "2000-02-29T12:00:00.5Z",
+        ] {
+            assert_eq!(input.parse::<Timestamp>().unwrap().to_string(), input);
+        }
+    }
+
+    #[test]
+    fn application_timestamps() {
+        let env = CfEnv::new(HashMap::from([(
+            "VCAP_APPLICATION",
+            r#"{
+                "cf_api": "https://api.example.io",
+                "limits": { "fds": 12384, "mem": 512, "disk": 1024 },
+                "application_name": "my-backend",
+                "application_uris": [],
+                "name": "my-backend",
+                "space_name": "production",
+                "space_id": "29349a46-ff0c-447e-bde0-db1be814f564",
+                "organization_id": "26421037-ab23-4b51-99f8-9f5a6251fd26",
+                "organization_name": "Project_One",
+                "uris": [],
+                "process_id": "d8304a62-2df7-41d5-9211-0917c2253591",
+                "process_type": "web",
+                "application_id": "d8304a62-2df7-41d5-9211-0917c2253591",
+                "version": "9fe9fe07-c7b7-415b-afa3-75fef5258d47",
+                "application_version": "9fe9fe07-c7b7-415b-afa3-75fef5258d47",
+                "started_at": "2013-08-12 00:05:29 +0000",
+                "started_at_timestamp": 1376265929,
+                "state_timestamp": "1376265929"
+            }"#,
+        )]));
+
+        let application = env.get_application_info().unwrap();
+        assert_eq!(
+            application.started_at,
+            Timestamp::from_unix_seconds(STARTED)
+        );
+        assert_eq!(application.started_at, application.state_timestamp);
+        assert_eq!(
+            application.uptime_at(UNIX_EPOCH + Duration::from_secs(STARTED as u64 + 60)),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(application.uptime_at(UNIX_EPOCH), None);
+
+        let serialized = serde_json::to_value(&application).unwrap();
+        assert_eq!(serialized["started_at"], "2013-08-12T00:05:29Z");
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn into_time() {
+        let date_time =
+            time::OffsetDateTime::try_from(Timestamp::from_unix_seconds(STARTED).unwrap()).unwrap();
+        assert_eq!(date_time.unix_timestamp(), STARTED);
+
+        // 9999-12-31T23:59:59Z is the last second `time` holds
+        let last = "253402300799".parse::<Timestamp>().unwrap();
+        assert_eq!(time::OffsetDateTime::try_from(last).unwrap().year(), 9999);
+        let beyond = serde_json::from_str::<Timestamp>("400000000000").unwrap();
+        assert_eq!(
+            time::OffsetDateTime::try_from(beyond).unwrap_err().kind(),
+            crate::ErrorKind::EnvMalformed
+        );
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn into_chrono() {
+        use chrono::Datelike;
+
+        let date_time = chrono::DateTime::<chrono::Utc>::try_from(
+            Timestamp::from_unix_seconds(STARTED).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(date_time.timestamp(), STARTED);
+
+        let before_epoch = "1969-12-31T23:59:59.5Z".parse::<Timestamp>().unwrap();
+        assert_eq!(
+            chrono::DateTime::<chrono::Utc>::try_from(before_epoch)
+                .unwrap()
+                .timestamp_millis(),
+            -500
+        );
+        let last = Timestamp::from_unix_seconds(253_402_300_799).unwrap();
+        assert_eq!(
+            chrono::DateTime::<chrono::Utc>::try_from(last)
+                .unwrap()
+                .year(),
+            9999
+        );
+        let beyond = serde_json::from_str::<Timestamp>("10000000000000").unwrap();
+        assert!(chrono::DateTime::<chrono::Utc>::try_from(beyond).is_err());
+    }
+}
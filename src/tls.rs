@@ -0,0 +1,149 @@
+//! rustls configurations built from the instance identity certificate, requires the `rustls` feature
+use crate::constants::{CF_INSTANCE_CERT, CF_SYSTEM_CERT_PATH};
+use crate::enums::Error;
+use crate::identity::InstanceIdentity;
+use rustls::crypto::CryptoProvider;
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+use rustls_pki_types::CertificateDer;
+use std::sync::Arc;
+
+/// The process wide default crypto provider if one is installed, ring otherwise
+pub(crate) fn crypto_provider() -> Arc<CryptoProvider> {
+    match CryptoProvider::get_default() {
+        Some(provider) => provider.clone(),
+        None => Arc::new(rustls::crypto::ring::default_provider()),
+    }
+}
+
+/// Builds a root store of the given CA certificates, e.g. from [`crate::get_system_certificates`]
+pub fn root_store(certificates: Vec<CertificateDer<'static>>) -> Result<RootCertStore, Error> {
+    let mut roots = RootCertStore::empty();
+    let (_, ignored) = roots.add_parsable_certificates(certificates);
+
+    if roots.is_empty() {
+        return Err(Error::EnvMalformed(
+            CF_SYSTEM_CERT_PATH.to_string(),
+            format!("Doesn't contain any usable CA certificate, {ignored} were ignored"),
+        ));
+    }
+    Ok(roots)
+}
+
+fn malformed(err: rustls::Error) -> Error {
+    Error::EnvMalformed(CF_INSTANCE_CERT.to_string(), err.to_string())
+}
+
+impl InstanceIdentity {
+    /// Builds a server config presenting the instance identity certificate and requiring client certificates signed by one of `roots`
+    ///
+    /// This is what container to container traffic with mutual tls needs, the clients present their own instance identity certificates.
+    pub fn server_config(&self, roots: RootCertStore) -> Result<ServerConfig, Error> {
+        let provider = crypto_provider();
+        let verifier =
+            WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                .build()
+                .map_err(|err| malformed(rustls::Error::General(err.to_string())))?;
+
+        ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(malformed)?
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(self.certificate.chain.clone(), self.key.clone_key())
+            .map_err(malformed)
+    }
+
+    /// Builds a client config trusting `roots` and presenting the instance identity certificate as client certificate
+    pub fn client_config(&self, roots: RootCertStore) -> Result<ClientConfig, Error> {
+        ClientConfig::builder_with_provider(crypto_provider())
+            .with_safe_default_protocol_versions()
+            .map_err(malformed)?
+            .with_root_certificates(roots)
+            .with_client_auth_cert(self.certificate.chain.clone(), self.key.clone_key())
+            .map_err(malformed)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::CfEnv;
+    use rustls::{ClientConnection, ServerConnection};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    const CERT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/instance.crt");
+    const KEY_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/instance.key");
+    const SYSTEM_CERT_PATH: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/system-certificates"
+    );
+
+    fn env() -> CfEnv<HashMap<&'static str, &'static str>> {
+        CfEnv::new(HashMap::from([
+            ("CF_INSTANCE_CERT", CERT_PATH),
+            ("CF_INSTANCE_KEY", KEY_PATH),
+            ("CF_SYSTEM_CERT_PATH", SYSTEM_CERT_PATH),
+        ]))
+    }
+
+    pub(crate) fn handshake(
+        client: &mut ClientConnection,
+        server: &mut ServerConnection,
+    ) -> Result<(), rustls::Error> {
+        let mut buffer = Vec::new();
+        while client.is_handshaking() || server.is_handshaking() {
+            buffer.clear();
+            client.write_tls(&mut buffer).unwrap();
+            server.read_tls(&mut buffer.as_slice()).unwrap();
+            server.process_new_packets()?;
+
+            buffer.clear();
+            server.write_tls(&mut buffer).unwrap();
+            client.read_tls(&mut buffer.as_slice()).unwrap();
+            client.process_new_packets()?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn mutual_tls_handshake() {
+        let env = env();
+        let server_config = Arc::new(env.get_tls_server_config().unwrap());
+        let client_config = Arc::new(env.get_tls_client_config().unwrap());
+
+        let mut server = ServerConnection::new(server_config).unwrap();
+        let mut client =
+            ClientConnection::new(client_config, "localhost".try_into().unwrap()).unwrap();
+
+        handshake(&mut client, &mut server).unwrap();
+
+        let peer = server.peer_certificates().unwrap();
+        assert_eq!(&peer[0], env.get_instance_cert().unwrap().leaf());
+    }
+
+    #[test]
+    fn server_rejects_anonymous_clients() {
+        let env = env();
+        let server_config = Arc::new(env.get_tls_server_config().unwrap());
+        let client_config =
+            rustls::ClientConfig::builder_with_provider(crate::tls::crypto_provider())
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(
+                    crate::tls::root_store(env.get_system_certificates().unwrap()).unwrap(),
+                )
+                .with_no_client_auth();
+
+        let mut server = ServerConnection::new(server_config).unwrap();
+        let mut client =
+            ClientConnection::new(Arc::new(client_config), "localhost".try_into().unwrap())
+                .unwrap();
+
+        assert!(handshake(&mut client, &mut server).is_err());
+    }
+
+    #[test]
+    fn empty_root_store() {
+        assert!(crate::tls::root_store(Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,34 @@
+#!/usr/bin/env sh
+# Regenerates the fixture certificates used by the tests.
+# Mimics the Diego instance identity CA and certificates, valid for 100 years.
+set -e
+cd "$(dirname "$0")"
+
+DAYS=36500
+ORG=26421037-ab23-4b51-99f8-9f5a6251fd26
+SPACE=29349a46-ff0c-447e-bde0-db1be814f564
+APP=d8304a62-2df7-41d5-9211-0917c2253591
+
+openssl ecparam -name prime256v1 -genkey -noout -out ca.key.tmp
+openssl pkcs8 -topk8 -nocrypt -in ca.key.tmp -out ca.key
+openssl req -x509 -new -key ca.key -days "$DAYS" -subj "/CN=instanceIdentityCA" \
+  -addext "basicConstraints=critical,CA:TRUE" -addext "keyUsage=critical,keyCertSign,cRLSign" -out ca.crt
+
+issue() {
+  name=$1
+  subject=$2
+  openssl ecparam -name prime256v1 -genkey -noout -out "$name.key.tmp"
+  openssl pkcs8 -topk8 -nocrypt -in "$name.key.tmp" -out "$name.key"
+  openssl req -new -key "$name.key" -subj "$subject" -out "$name.csr"
+  printf "basicConstraints=CA:FALSE\nkeyUsage=critical,digitalSignature,keyAgreement\nextendedKeyUsage=serverAuth,clientAuth\nsubjectAltName=DNS:localhost,IP:127.0.0.1\n" > "$name.ext"
+  openssl x509 -req -in "$name.csr" -CA ca.crt -CAkey ca.key -CAcreateserial -days "$DAYS" -extfile "$name.ext" -out "$name.leaf"
+  cat "$name.leaf" ca.crt > "$name.crt"
+  rm "$name.csr" "$name.ext" "$name.leaf" "$name.key.tmp"
+}
+
+issue instance "/OU=organization:$ORG/OU=space:$SPACE/OU=app:$APP/CN=046463bc-1ba9-4046-bf5a-bd95672ee871"
+issue rotated "/OU=organization:$ORG/OU=space:$SPACE/OU=app:$APP/CN=046463bc-1ba9-4046-bf5a-bd95672ee871"
+
+mkdir -p system-certificates
+cp ca.crt system-certificates/instance-identity-ca.crt
+rm -f ca.key.tmp ca.srl